@@ -0,0 +1,2 @@
+-- Add ENS contenthash support to the names table
+ALTER TABLE names ADD COLUMN contenthash BYTEA;
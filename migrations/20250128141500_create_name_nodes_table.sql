@@ -0,0 +1,9 @@
+-- Store the ENS namehash (node) of each name so it can be looked up directly.
+-- Names resolve under every configured ENS domain, so keep one node per name and domain.
+-- Existing names are backfilled by `POST /system/backfill-nodes`, since keccak256 isn't available in Postgres.
+CREATE TABLE name_nodes (
+    node BYTEA PRIMARY KEY,
+    username VARCHAR NOT NULL REFERENCES names (username) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_name_nodes_username ON name_nodes (username);
@@ -0,0 +1,3 @@
+-- Add ENS pubkey record support to the names table
+ALTER TABLE names ADD COLUMN pubkey_x BYTEA;
+ALTER TABLE names ADD COLUMN pubkey_y BYTEA;
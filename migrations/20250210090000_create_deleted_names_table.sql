@@ -0,0 +1,8 @@
+-- Create deleted_names table, keeping a tombstone for names removed on user request
+CREATE TABLE deleted_names (
+    username VARCHAR PRIMARY KEY,
+    deleted_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- Registration checks tombstones case-insensitively, like names and old_names
+CREATE INDEX IF NOT EXISTS deleted_names_username_lower_idx ON deleted_names (LOWER(username));
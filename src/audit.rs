@@ -0,0 +1,183 @@
+use http::StatusCode;
+
+use crate::types::ErrorResponse;
+
+/// A mutation of a username, recorded once it completes. Only identifies who changed what, never proof material.
+#[derive(Debug)]
+pub struct Mutation {
+	action: &'static str,
+	username: String,
+	address: Option<String>,
+	nullifier_hash: String,
+}
+
+impl Mutation {
+	pub const fn new(
+		action: &'static str,
+		username: String,
+		address: Option<String>,
+		nullifier_hash: String,
+	) -> Self {
+		Self {
+			action,
+			username,
+			address,
+			nullifier_hash,
+		}
+	}
+
+	/// Emit the audit log entry for this mutation's outcome.
+	pub fn record(&self, outcome: &Result<StatusCode, ErrorResponse>) {
+		let status = match outcome {
+			Ok(status) => *status,
+			Err(error) => error.status(),
+		};
+
+		tracing::info!(
+			target: "audit",
+			action = self.action,
+			username = %self.username,
+			address = self.address.as_deref(),
+			nullifier_hash = %self.nullifier_hash,
+			outcome = if outcome.is_ok() { "success" } else { "failure" },
+			status = status.as_u16(),
+			"username mutation"
+		);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		collections::BTreeSet,
+		io,
+		sync::{Arc, Mutex},
+	};
+
+	use super::*;
+
+	#[derive(Clone, Default)]
+	struct Capture(Arc<Mutex<Vec<u8>>>);
+
+	impl io::Write for Capture {
+		fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+			self.0.lock().unwrap().extend_from_slice(buf);
+			Ok(buf.len())
+		}
+
+		fn flush(&mut self) -> io::Result<()> {
+			Ok(())
+		}
+	}
+
+	/// Record the mutation and return the fields of the logged entry.
+	fn entry(
+		mutation: &Mutation,
+		outcome: &Result<StatusCode, ErrorResponse>,
+	) -> serde_json::Map<String, serde_json::Value> {
+		let capture = Capture::default();
+		let subscriber = tracing_subscriber::fmt()
+			.json()
+			.with_writer({
+				let capture = capture.clone();
+				move || capture.clone()
+			})
+			.finish();
+
+		tracing::subscriber::with_default(subscriber, || mutation.record(outcome));
+
+		let output = capture.0.lock().unwrap().clone();
+		let line: serde_json::Value = serde_json::from_slice(&output).unwrap();
+		assert_eq!(line["target"], "audit");
+
+		line["fields"].as_object().unwrap().clone()
+	}
+
+	fn keys(entry: &serde_json::Map<String, serde_json::Value>) -> BTreeSet<&str> {
+		entry.keys().map(String::as_str).collect()
+	}
+
+	#[test]
+	fn audits_registrations() {
+		let mutation = Mutation::new(
+			"register",
+			"alice".to_string(),
+			Some("0x0000000000000000000000000000000000000001".to_string()),
+			"0x1".to_string(),
+		);
+
+		let entry = entry(&mutation, &Ok(StatusCode::CREATED));
+
+		assert_eq!(
+			keys(&entry),
+			BTreeSet::from([
+				"message",
+				"action",
+				"username",
+				"address",
+				"nullifier_hash",
+				"outcome",
+				"status"
+			])
+		);
+		assert_eq!(entry["action"], "register");
+		assert_eq!(entry["username"], "alice");
+		assert_eq!(
+			entry["address"],
+			"0x0000000000000000000000000000000000000001"
+		);
+		assert_eq!(entry["nullifier_hash"], "0x1");
+		assert_eq!(entry["outcome"], "success");
+		assert_eq!(entry["status"], 201);
+	}
+
+	#[test]
+	fn audits_renames() {
+		let mutation = Mutation::new("rename", "alice".to_string(), None, "0x1".to_string());
+
+		let entry = entry(
+			&mutation,
+			&Err(ErrorResponse::validation_error(
+				"Username is already taken".to_string(),
+			)),
+		);
+
+		// Renames don't change the address, so none is logged.
+		assert_eq!(
+			keys(&entry),
+			BTreeSet::from([
+				"message",
+				"action",
+				"username",
+				"nullifier_hash",
+				"outcome",
+				"status"
+			])
+		);
+		assert_eq!(entry["action"], "rename");
+		assert_eq!(entry["outcome"], "failure");
+		assert_eq!(entry["status"], 422);
+	}
+
+	#[test]
+	fn audits_updates() {
+		let mutation = Mutation::new(
+			"update",
+			"alice".to_string(),
+			Some("0x0000000000000000000000000000000000000002".to_string()),
+			"0x1".to_string(),
+		);
+
+		let entry = entry(&mutation, &Ok(StatusCode::OK));
+
+		assert!(!entry.contains_key("proof"));
+		assert!(!entry.contains_key("merkle_root"));
+		assert_eq!(entry["action"], "update");
+		assert_eq!(
+			entry["address"],
+			"0x0000000000000000000000000000000000000002"
+		);
+		assert_eq!(entry["outcome"], "success");
+		assert_eq!(entry["status"], 200);
+	}
+}
@@ -1,9 +1,17 @@
 use axum::Extension;
-use std::{collections::HashSet, sync::Arc};
+use std::{
+	collections::{HashMap, HashSet},
+	sync::Arc,
+};
+
+use crate::utils::secure_eq;
 
 #[allow(clippy::module_name_repetitions)]
 pub type BlocklistExt = Extension<Arc<Blocklist>>;
 
+/// Usernames the static routes next to `GET /api/v1/:name` would shadow, so they could never be looked up.
+const ROUTE_NAMES: &[&str] = &["available", "avatars"];
+
 /// A blocklist of usernames and substrings.
 #[derive(Debug)]
 pub struct Blocklist {
@@ -11,6 +19,8 @@ pub struct Blocklist {
 	names: HashSet<Box<str>>,
 	/// A list of substrings that are not allowed in usernames
 	substrings: Vec<String>,
+	/// Usernames reserved for a specific World ID, keyed by lowercase username with the owner's lowercase nullifier hash as value
+	reservations: HashMap<Box<str>, String>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -21,34 +31,112 @@ pub enum Error {
 	Contains(String),
 }
 
+impl Error {
+	/// Machine-readable code identifying the violated rule.
+	pub const fn code(&self) -> &'static str {
+		match self {
+			Self::Reserved => "reserved",
+			Self::Contains(_) => "blocked_substring",
+		}
+	}
+}
+
 impl Blocklist {
 	/// Create a new blocklist from the given strings.
 	/// - `blocked_names` is a comma-separated list of blocked usernames
 	/// - `blocked_substrings` is a comma-separated list of blocked substrings
-	pub fn new(blocked_names: &str, blocked_substrings: &str) -> Self {
+	/// - `reserved_for_owners` is a comma-separated list of `username:nullifier_hash` pairs
+	pub fn new(blocked_names: &str, blocked_substrings: &str, reserved_for_owners: &str) -> Self {
 		let names = blocked_names.split(',').map(|s| s.trim().into()).collect();
 		let substrings = blocked_substrings
 			.split(',')
 			.map(|s| s.trim().into())
 			.collect();
+		let reservations = reserved_for_owners
+			.split(',')
+			.filter_map(|s| s.split_once(':'))
+			.map(|(name, owner)| {
+				(
+					name.trim().to_lowercase().into(),
+					owner.trim().to_lowercase(),
+				)
+			})
+			.collect();
 
-		Self { names, substrings }
+		Self {
+			names,
+			substrings,
+			reservations,
+		}
 	}
 
-	/// Check if a username is blocked.
-	pub fn ensure_valid(&self, username: &str) -> Result<(), Error> {
-		if self.names.contains(username) {
-			return Err(Error::Reserved);
-		};
+	/// Find every blocklist rule a username violates when requested by the given World ID.
+	pub fn violations(&self, username: &str, nullifier_hash: &str) -> Vec<Error> {
+		let mut violations = Vec::new();
+
+		let reserved_for_other = self
+			.reservations
+			.get(username.to_lowercase().as_str())
+			.is_some_and(|owner| !secure_eq(owner, &nullifier_hash.to_lowercase()));
+
+		if self.names.contains(username) || ROUTE_NAMES.contains(&username) || reserved_for_other {
+			violations.push(Error::Reserved);
+		}
+
+		violations.extend(
+			self.substrings
+				.iter()
+				.filter(|s| username.contains(s.as_str()))
+				.map(|s| Error::Contains(s.clone())),
+		);
+
+		violations
+	}
+}
 
-		if let Some(substring) = self
-			.substrings
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn codes(blocklist: &Blocklist, username: &str, nullifier_hash: &str) -> Vec<&'static str> {
+		blocklist
+			.violations(username, nullifier_hash)
 			.iter()
-			.find(|s| username.contains(s.as_str()))
-		{
-			return Err(Error::Contains(substring.clone()));
-		};
+			.map(Error::code)
+			.collect()
+	}
+
+	#[test]
+	fn reserves_usernames_shadowed_by_routes() {
+		let blocklist = Blocklist::new("admin", "badword", "");
+
+		assert_eq!(codes(&blocklist, "avatars", "0x1"), ["reserved"]);
+		assert_eq!(codes(&blocklist, "available", "0x1"), ["reserved"]);
+		assert!(codes(&blocklist, "avatar", "0x1").is_empty());
+	}
+
+	#[test]
+	fn reserved_usernames_are_available_to_their_owner() {
+		let blocklist = Blocklist::new("", "badword", "alice:0xabc");
+
+		assert!(codes(&blocklist, "alice", "0xabc").is_empty());
+		assert!(codes(&blocklist, "alice", "0xABC").is_empty());
+	}
+
+	#[test]
+	fn reserved_usernames_are_blocked_for_everyone_else() {
+		let blocklist = Blocklist::new("", "badword", "alice:0xabc");
+
+		assert_eq!(codes(&blocklist, "alice", "0xdef"), ["reserved"]);
+		assert!(codes(&blocklist, "bobby", "0xdef").is_empty());
+	}
+
+	#[test]
+	fn reservations_ignore_case() {
+		let blocklist = Blocklist::new("", "badword", "Alice:0xabc");
 
-		Ok(())
+		assert_eq!(codes(&blocklist, "ALICE", "0xdef"), ["reserved"]);
+		assert_eq!(codes(&blocklist, "alice", "0xdef"), ["reserved"]);
+		assert!(codes(&blocklist, "aLiCe", "0xabc").is_empty());
 	}
 }
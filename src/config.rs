@@ -1,16 +1,26 @@
-use anyhow::Context;
+use anyhow::{bail, Context};
 use axum::Extension;
+use http::{
+	header::{InvalidHeaderName, InvalidHeaderValue},
+	HeaderName, HeaderValue,
+};
 use idkit::session::AppId;
 use regex::Regex;
 use sqlx::{migrate::MigrateError, postgres::PgPoolOptions, PgPool};
 use std::{
+	collections::HashMap,
 	env::{self, VarError},
 	num::ParseIntError,
+	str::ParseBoolError,
 	sync::{Arc, LazyLock},
 	time::Duration,
 };
 
-use crate::blocklist::{Blocklist, BlocklistExt};
+use crate::{
+	blocklist::{Blocklist, BlocklistExt},
+	latency::Latency,
+	verify,
+};
 
 #[allow(clippy::module_name_repetitions)]
 pub type ConfigExt = Extension<Arc<Config>>;
@@ -19,15 +29,50 @@ pub static USERNAME_REGEX: LazyLock<Regex> =
 	LazyLock::new(|| Regex::new(r"^[a-z]\w{2,13}[a-z0-9]$").unwrap());
 pub static DEVICE_USERNAME_REGEX: LazyLock<Regex> =
 	LazyLock::new(|| Regex::new(r"^[a-z]\w{2,13}[a-z0-9]\.\d{4}$").unwrap());
+/// The characters `\w` accepts in the username regexes, checked on their own for a clearer error.
+pub static USERNAME_CHARACTERS_REGEX: LazyLock<Regex> =
+	LazyLock::new(|| Regex::new(r"^\w*$").unwrap());
 pub static USERNAME_SEARCH_REGEX: LazyLock<Regex> =
 	LazyLock::new(|| Regex::new(r"^[a-z]\w{0,13}[a-z0-9]$").unwrap());
 
+/// The kind of deployment the server runs in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Environment {
+	Development,
+	Production,
+}
+
 #[derive(Debug)]
 pub struct Config {
 	pub wld_app_id: AppId,
-	pub ens_domain: String,
+	/// Every ENS domain names resolve under, starting with the primary one.
+	pub ens_domains: Vec<String>,
 	pub private_key: String,
 	pub developer_portal_url: String,
+	pub developer_portal_verify_path: String,
+	pub verify_timeout: Duration,
+	pub verify_max_retries: u32,
+	pub verify_retry_min_delay: Duration,
+	pub verify_retry_max_delay: Duration,
+	pub cors_max_age: Duration,
+	pub cors_allowed_origins: Option<Vec<HeaderValue>>,
+	pub cors_allowed_headers: Vec<HeaderName>,
+	pub ens_verification_level_record: bool,
+	pub ens_error_classification: bool,
+	pub reject_empty_queries: bool,
+	pub query_multiple_max_results: usize,
+	pub avatars_max_batch_size: usize,
+	pub available_max_batch_size: usize,
+	pub ens_default_text_records: HashMap<String, String>,
+	pub search_cache_max_age: u64,
+	pub query_single_cache_max_age: u64,
+	pub search_min_query_length: usize,
+	pub shutdown_timeout: Duration,
+	pub max_body_size: usize,
+	pub max_usernames_per_address: Option<i64>,
+	pub system_api_key: Option<String>,
+	pub system_hmac_secret: Option<String>,
+	pub register_batch_max_size: usize,
 	db_client: Option<PgPool>,
 	db_read_client: Option<PgPool>,
 	blocklist: Option<Blocklist>,
@@ -36,6 +81,8 @@ pub struct Config {
 pub struct Db {
 	pub read_only: PgPool,
 	pub read_write: PgPool,
+	/// Latency of the queries run against each pool.
+	pub latency: Arc<Latency>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -47,28 +94,41 @@ pub enum Error {
 	#[error(transparent)]
 	ChainId(#[from] ParseIntError),
 	#[error(transparent)]
+	Flag(#[from] ParseBoolError),
+	#[error(transparent)]
+	Origin(#[from] InvalidHeaderValue),
+	#[error(transparent)]
+	HeaderName(#[from] InvalidHeaderName),
+	#[error(transparent)]
 	EnvWithContext(#[from] anyhow::Error),
 }
 
 impl Config {
 	pub async fn from_env() -> Result<Self, Error> {
+		let environment = parse_environment(&env::var("APP_ENV").unwrap_or_default())?;
+		let ens_domains = parse_ens_domains(
+			&env::var("ENS_DOMAIN").context("ENS_DOMAIN environment variable not set")?,
+		)?;
+
 		let blocklist = Blocklist::new(
 			&env::var("RESERVED_USERNAMES")
 				.context("RESERVED_USERNAMES environment variable not set")?,
 			&env::var("BLOCKED_SUBSTRINGS")
 				.context("BLOCKED_SUBSTRINGS environment variable not set")?,
+			&env::var("RESERVED_FOR_OWNERS").unwrap_or_default(),
 		);
 
-		let db_client = PgPoolOptions::new()
-			.max_connections(100)
-			.acquire_timeout(Duration::from_secs(3))
+		let pool_settings = PoolSettings::from_env()?;
+
+		let db_client = pool_settings
+			.write_options()
 			.connect(
 				&env::var("DATABASE_URL").context("DATABASE_URL environment variable not set")?,
 			)
 			.await?;
 
-		let db_read_client = PgPoolOptions::new()
-			.acquire_timeout(Duration::from_secs(3))
+		let db_read_client = pool_settings
+			.read_options()
 			.connect(
 				&env::var("DATABASE_READ_URL")
 					.context("DATABASE_READ_URL environment variable not set")?,
@@ -79,8 +139,7 @@ impl Config {
 			db_client: Some(db_client),
 			db_read_client: Some(db_read_client),
 			blocklist: Some(blocklist),
-			ens_domain: env::var("ENS_DOMAIN")
-				.context("ENS_DOMAIN environment variable not set")?,
+			ens_domains,
 			private_key: env::var("PRIVATE_KEY")
 				.context("PRIVATE_KEY environment variable not set")?,
 			wld_app_id: unsafe {
@@ -90,9 +149,82 @@ impl Config {
 			},
 			developer_portal_url: env::var("DEVELOPER_PORTAL_ENDPOINT")
 				.context("DEVELOPER_PORTAL_ENDPOINT environment variable not set")?,
+			developer_portal_verify_path: env::var("DEVELOPER_PORTAL_VERIFY_PATH").map_or_else(
+				|_| "api/v2/verify".to_string(),
+				|v| v.trim_matches('/').to_string(),
+			),
+			verify_timeout: Duration::from_secs(
+				env::var("VERIFY_TOTAL_TIMEOUT_SECS").map_or(Ok(10), |v| v.parse())?,
+			),
+			verify_max_retries: env::var("VERIFY_MAX_RETRIES").map_or(Ok(3), |v| v.parse())?,
+			verify_retry_min_delay: Duration::from_millis(
+				env::var("VERIFY_RETRY_MIN_DELAY_MS").map_or(Ok(100), |v| v.parse())?,
+			),
+			verify_retry_max_delay: Duration::from_millis(
+				env::var("VERIFY_RETRY_MAX_DELAY_MS").map_or(Ok(2000), |v| v.parse())?,
+			),
+			cors_max_age: Duration::from_secs(
+				env::var("CORS_MAX_AGE_SECS").map_or(Ok(3600), |v| v.parse())?,
+			),
+			cors_allowed_origins: parse_cors_origins(
+				env::var("CORS_ALLOWED_ORIGINS").ok().as_deref(),
+				environment,
+			)?,
+			cors_allowed_headers: parse_headers(
+				&env::var("CORS_ALLOWED_HEADERS")
+					.unwrap_or_else(|_| "content-type,if-none-match".to_string()),
+			)?,
+			ens_verification_level_record: env::var("ENS_VERIFICATION_LEVEL_RECORD")
+				.map_or(Ok(true), |v| v.parse())?,
+			ens_error_classification: env::var("ENS_ERROR_CLASSIFICATION")
+				.map_or(Ok(false), |v| v.parse())?,
+			reject_empty_queries: env::var("REJECT_EMPTY_QUERIES")
+				.map_or(Ok(false), |v| v.parse())?,
+			query_multiple_max_results: env::var("QUERY_MULTIPLE_MAX_RESULTS")
+				.map_or(Ok(1000), |v| v.parse())?,
+			avatars_max_batch_size: env::var("AVATARS_MAX_BATCH_SIZE")
+				.map_or(Ok(100), |v| v.parse())?,
+			available_max_batch_size: env::var("AVAILABLE_MAX_BATCH_SIZE")
+				.map_or(Ok(50), |v| v.parse())?,
+			ens_default_text_records: parse_text_records(
+				&env::var("ENS_DEFAULT_TEXT_RECORDS").unwrap_or_else(|_| "email,url".to_string()),
+			),
+			search_cache_max_age: env::var("SEARCH_CACHE_MAX_AGE_SECS")
+				.map_or(Ok(300), |v| v.parse())?,
+			query_single_cache_max_age: env::var("QUERY_SINGLE_CACHE_MAX_AGE_SECS")
+				.map_or(Ok(60), |v| v.parse())?,
+			search_min_query_length: env::var("SEARCH_MIN_QUERY_LENGTH")
+				.map_or(Ok(1), |v| v.parse())?,
+			shutdown_timeout: Duration::from_secs(
+				env::var("SHUTDOWN_TIMEOUT_SECS").map_or(Ok(30), |v| v.parse())?,
+			),
+			max_body_size: env::var("MAX_BODY_SIZE_BYTES").map_or(Ok(256 * 1024), |v| v.parse())?,
+			max_usernames_per_address: env::var("MAX_USERNAMES_PER_ADDRESS")
+				.ok()
+				.map(|v| v.parse())
+				.transpose()?,
+			system_api_key: env::var("SYSTEM_API_KEY").ok(),
+			system_hmac_secret: env::var("SYSTEM_HMAC_SECRET").ok(),
+			register_batch_max_size: env::var("REGISTER_BATCH_MAX_SIZE")
+				.map_or(Ok(500), |v| v.parse())?,
 		})
 	}
 
+	/// Strip any configured ENS domain from a fully-qualified name, returning the username.
+	pub fn strip_ens_domain<'a>(&self, name: &'a str) -> Option<&'a str> {
+		strip_ens_domain(&self.ens_domains, name)
+	}
+
+	/// How proof verification against the Developer Portal is bounded and retried.
+	pub const fn verify_options(&self) -> verify::Options {
+		verify::Options {
+			timeout: self.verify_timeout,
+			max_retries: self.verify_max_retries,
+			min_delay: self.verify_retry_min_delay,
+			max_delay: self.verify_retry_max_delay,
+		}
+	}
+
 	pub async fn migrate_database(&self) -> Result<(), MigrateError> {
 		sqlx::migrate!().run(self.db_client.as_ref().unwrap()).await
 	}
@@ -101,6 +233,7 @@ impl Config {
 		Extension(Db {
 			read_only: self.db_read_client.take().unwrap(),
 			read_write: self.db_client.take().unwrap(),
+			latency: Arc::new(Latency::default()),
 		})
 	}
 
@@ -112,3 +245,350 @@ impl Config {
 		Extension(Arc::new(self))
 	}
 }
+
+#[cfg(test)]
+impl Config {
+	/// A config with the default settings, resolving names under `world.id`, and without database pools or a blocklist.
+	pub fn for_tests() -> Self {
+		Self {
+			wld_app_id: unsafe { AppId::new_unchecked("app_staging_test".to_string()) },
+			ens_domains: vec!["world.id".to_string()],
+			// Anvil's first development key, never used outside tests.
+			private_key: "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80"
+				.to_string(),
+			developer_portal_url: "https://developer.worldcoin.org".to_string(),
+			developer_portal_verify_path: "api/v2/verify".to_string(),
+			verify_timeout: Duration::from_secs(10),
+			verify_max_retries: 3,
+			verify_retry_min_delay: Duration::from_millis(100),
+			verify_retry_max_delay: Duration::from_millis(2000),
+			cors_max_age: Duration::from_secs(3600),
+			cors_allowed_origins: None,
+			cors_allowed_headers: parse_headers("content-type,if-none-match").unwrap(),
+			ens_verification_level_record: true,
+			ens_error_classification: false,
+			reject_empty_queries: false,
+			query_multiple_max_results: 1000,
+			avatars_max_batch_size: 100,
+			available_max_batch_size: 50,
+			ens_default_text_records: parse_text_records("email,url"),
+			search_cache_max_age: 300,
+			query_single_cache_max_age: 60,
+			search_min_query_length: 1,
+			shutdown_timeout: Duration::from_secs(30),
+			max_body_size: 256 * 1024,
+			max_usernames_per_address: None,
+			system_api_key: None,
+			system_hmac_secret: None,
+			register_batch_max_size: 500,
+			db_client: None,
+			db_read_client: None,
+			blocklist: None,
+		}
+	}
+}
+
+/// Connection pool sizing for the read-write and read-only databases.
+struct PoolSettings {
+	max_connections: u32,
+	read_max_connections: u32,
+	read_min_connections: u32,
+	acquire_timeout: Duration,
+}
+
+impl PoolSettings {
+	fn from_env() -> Result<Self, Error> {
+		Self::from_vars(|name| env::var(name).ok())
+	}
+
+	/// Read the settings with `var`, falling back to the defaults for unset ones.
+	fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, Error> {
+		Ok(Self {
+			max_connections: var("DB_MAX_CONNECTIONS").map_or(Ok(100), |v| v.parse())?,
+			read_max_connections: var("DB_READ_MAX_CONNECTIONS").map_or(Ok(10), |v| v.parse())?,
+			read_min_connections: var("DB_READ_MIN_CONNECTIONS").map_or(Ok(0), |v| v.parse())?,
+			acquire_timeout: Duration::from_secs(
+				var("DB_ACQUIRE_TIMEOUT_SECS").map_or(Ok(3), |v| v.parse())?,
+			),
+		})
+	}
+
+	fn write_options(&self) -> PgPoolOptions {
+		PgPoolOptions::new()
+			.max_connections(self.max_connections)
+			.acquire_timeout(self.acquire_timeout)
+	}
+
+	fn read_options(&self) -> PgPoolOptions {
+		PgPoolOptions::new()
+			.max_connections(self.read_max_connections)
+			.min_connections(self.read_min_connections)
+			.acquire_timeout(self.acquire_timeout)
+	}
+}
+
+/// Parse a comma-separated list of origins allowed to make cross-origin requests.
+fn parse_origins(origins: &str) -> Result<Vec<HeaderValue>, InvalidHeaderValue> {
+	origins
+		.split(',')
+		.map(str::trim)
+		.filter(|origin| !origin.is_empty())
+		.map(HeaderValue::from_str)
+		.collect()
+}
+
+/// Parse the allowed CORS origins. Any origin is only allowed in development, so production refuses to start without a list.
+fn parse_cors_origins(
+	origins: Option<&str>,
+	environment: Environment,
+) -> anyhow::Result<Option<Vec<HeaderValue>>> {
+	match (origins, environment) {
+		(Some(origins), _) => Ok(Some(parse_origins(origins)?)),
+		(None, Environment::Development) => Ok(None),
+		(None, Environment::Production) => bail!(
+			"CORS_ALLOWED_ORIGINS must be set in production (set APP_ENV=development to allow any origin)"
+		),
+	}
+}
+
+/// Parse a comma-separated list of request headers browsers may send cross-origin.
+fn parse_headers(headers: &str) -> Result<Vec<HeaderName>, InvalidHeaderName> {
+	headers
+		.split(',')
+		.map(str::trim)
+		.filter(|header| !header.is_empty())
+		.map(|header| HeaderName::from_bytes(header.as_bytes()))
+		.collect()
+}
+
+/// Parse the deployment environment. Unset means production, so development-only fallbacks must be opted into.
+fn parse_environment(environment: &str) -> anyhow::Result<Environment> {
+	match environment.trim().to_lowercase().as_str() {
+		"development" | "dev" | "local" => Ok(Environment::Development),
+		"" | "production" | "prod" | "staging" => Ok(Environment::Production),
+		other => bail!("APP_ENV {other:?} is not a known environment"),
+	}
+}
+
+/// Parse a comma-separated list of `key` or `key=value` text records. Keys without a value resolve to an empty string.
+fn parse_text_records(records: &str) -> HashMap<String, String> {
+	records
+		.split(',')
+		.map(str::trim)
+		.filter(|record| !record.is_empty())
+		.map(|record| {
+			let (key, value) = record.split_once('=').unwrap_or((record, ""));
+			(key.trim().to_string(), value.trim().to_string())
+		})
+		.collect()
+}
+
+/// Parse a comma-separated list of ENS domains. The first one is the primary domain.
+fn parse_ens_domains(domains: &str) -> anyhow::Result<Vec<String>> {
+	let domains = domains
+		.split(',')
+		.filter(|domain| !domain.trim().is_empty())
+		.map(parse_ens_domain)
+		.collect::<anyhow::Result<Vec<_>>>()?;
+
+	if domains.is_empty() {
+		bail!("ENS_DOMAIN cannot be empty");
+	}
+
+	Ok(domains)
+}
+
+/// Strip whichever of `domains` a fully-qualified name is under.
+/// When several domains match (e.g. `world.id` and `x.world.id`), the longest one wins.
+fn strip_ens_domain<'a>(domains: &[String], name: &'a str) -> Option<&'a str> {
+	domains
+		.iter()
+		.filter_map(|domain| name.strip_suffix(&format!(".{domain}")))
+		.min_by_key(|username| username.len())
+}
+
+/// Normalize an ENS domain, rejecting values that would never match a resolved name.
+fn parse_ens_domain(domain: &str) -> anyhow::Result<String> {
+	let domain = domain.trim().to_lowercase();
+
+	if domain.is_empty() {
+		bail!("ENS_DOMAIN cannot be empty");
+	}
+
+	for label in domain.split('.') {
+		if label.is_empty() {
+			bail!("ENS_DOMAIN {domain:?} contains an empty label");
+		}
+
+		if label.len() > 63 {
+			bail!("ENS_DOMAIN label {label:?} is longer than 63 characters");
+		}
+
+		if label.starts_with('-') || label.ends_with('-') {
+			bail!("ENS_DOMAIN label {label:?} cannot start or end with a hyphen");
+		}
+
+		if !label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+			bail!("ENS_DOMAIN label {label:?} contains invalid characters");
+		}
+	}
+
+	Ok(domain)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn defaults_to_production() {
+		assert_eq!(parse_environment("").unwrap(), Environment::Production);
+		assert_eq!(
+			parse_environment("production").unwrap(),
+			Environment::Production
+		);
+		assert_eq!(
+			parse_environment(" Development ").unwrap(),
+			Environment::Development
+		);
+		assert!(parse_environment("prdo").is_err());
+	}
+
+	#[test]
+	fn parses_allowed_headers() {
+		assert_eq!(
+			parse_headers("Content-Type, if-none-match,").unwrap(),
+			[http::header::CONTENT_TYPE, http::header::IF_NONE_MATCH]
+		);
+		assert!(parse_headers("not a header").is_err());
+	}
+
+	#[test]
+	fn parses_default_text_records() {
+		let records = parse_text_records(" email, url=https://world.org ,, com.discord = ");
+
+		assert_eq!(
+			records,
+			HashMap::from([
+				("email".to_string(), String::new()),
+				("url".to_string(), "https://world.org".to_string()),
+				("com.discord".to_string(), String::new()),
+			])
+		);
+		assert!(parse_text_records("").is_empty());
+	}
+
+	#[test]
+	fn strips_configured_ens_domains() {
+		let domains = ["world.id".to_string(), "x.world.id".to_string()];
+
+		assert_eq!(strip_ens_domain(&domains, "alice.world.id"), Some("alice"));
+		assert_eq!(
+			strip_ens_domain(&domains, "alice.x.world.id"),
+			Some("alice")
+		);
+		assert_eq!(strip_ens_domain(&domains, "alice.eth"), None);
+		// The domain has to be a whole label, not just a suffix.
+		assert_eq!(strip_ens_domain(&domains, "aliceworld.id"), None);
+		assert_eq!(strip_ens_domain(&domains, "world.id"), None);
+	}
+
+	#[test]
+	fn production_requires_cors_origins() {
+		assert!(parse_cors_origins(None, Environment::Production).is_err());
+		assert_eq!(
+			parse_cors_origins(Some("https://world.org"), Environment::Production).unwrap(),
+			Some(vec![HeaderValue::from_static("https://world.org")])
+		);
+	}
+
+	#[test]
+	fn development_allows_any_cors_origin() {
+		assert_eq!(
+			parse_cors_origins(None, Environment::Development).unwrap(),
+			None
+		);
+	}
+
+	#[test]
+	fn accepts_ens_domains() {
+		assert_eq!(parse_ens_domain("world.id").unwrap(), "world.id");
+		assert_eq!(parse_ens_domain(" World.ID ").unwrap(), "world.id");
+		assert_eq!(parse_ens_domain("my-app.eth").unwrap(), "my-app.eth");
+		assert_eq!(parse_ens_domain("eth").unwrap(), "eth");
+	}
+
+	#[test]
+	fn rejects_invalid_ens_domains() {
+		let long_label = format!("{}.eth", "a".repeat(64));
+
+		for domain in [
+			"",
+			"  ",
+			".world.id",
+			"world.id.",
+			"world..id",
+			"-world.id",
+			"world-.id",
+			"wor_ld.id",
+			"wörld.id",
+			long_label.as_str(),
+		] {
+			assert!(
+				parse_ens_domain(domain).is_err(),
+				"{domain:?} should be rejected"
+			);
+		}
+	}
+
+	#[test]
+	fn parses_several_ens_domains() {
+		assert_eq!(
+			parse_ens_domains("world.id, X.World.id,").unwrap(),
+			["world.id", "x.world.id"]
+		);
+		assert!(parse_ens_domains("").is_err());
+		assert!(parse_ens_domains("world.id,-bad.id").is_err());
+	}
+
+	fn pool_settings(vars: &[(&str, &str)]) -> Result<PoolSettings, Error> {
+		PoolSettings::from_vars(|name| {
+			vars.iter()
+				.find(|(key, _)| *key == name)
+				.map(|(_, value)| (*value).to_string())
+		})
+	}
+
+	#[test]
+	fn defaults_pool_settings() {
+		let settings = pool_settings(&[]).unwrap();
+
+		assert_eq!(settings.max_connections, 100);
+		assert_eq!(settings.read_max_connections, 10);
+		assert_eq!(settings.read_min_connections, 0);
+		assert_eq!(settings.acquire_timeout, Duration::from_secs(3));
+	}
+
+	#[test]
+	fn reads_pool_settings() {
+		let settings = pool_settings(&[
+			("DB_MAX_CONNECTIONS", "20"),
+			("DB_READ_MAX_CONNECTIONS", "30"),
+			("DB_READ_MIN_CONNECTIONS", "5"),
+			("DB_ACQUIRE_TIMEOUT_SECS", "10"),
+		])
+		.unwrap();
+
+		assert_eq!(settings.max_connections, 20);
+		assert_eq!(settings.read_max_connections, 30);
+		assert_eq!(settings.read_min_connections, 5);
+		assert_eq!(settings.acquire_timeout, Duration::from_secs(10));
+	}
+
+	#[test]
+	fn rejects_invalid_pool_settings() {
+		assert!(pool_settings(&[("DB_MAX_CONNECTIONS", "many")]).is_err());
+		assert!(pool_settings(&[("DB_READ_MIN_CONNECTIONS", "-1")]).is_err());
+		assert!(pool_settings(&[("DB_ACQUIRE_TIMEOUT_SECS", "1.5")]).is_err());
+	}
+}
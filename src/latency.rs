@@ -0,0 +1,132 @@
+use std::{
+	sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+	time::{Duration, Instant},
+};
+
+/// Number of samples kept per pool.
+const SAMPLE_COUNT: usize = 128;
+
+/// Rolling latency samples of the queries run against each database pool.
+#[derive(Debug, Default)]
+pub struct Latency {
+	pub read_only: Samples,
+	pub read_write: Samples,
+}
+
+/// A fixed-size ring buffer of latency samples, in microseconds.
+#[derive(Debug)]
+pub struct Samples {
+	samples: [AtomicU64; SAMPLE_COUNT],
+	next: AtomicUsize,
+}
+
+impl Default for Samples {
+	fn default() -> Self {
+		Self {
+			samples: std::array::from_fn(|_| AtomicU64::new(0)),
+			next: AtomicUsize::new(0),
+		}
+	}
+}
+
+impl Samples {
+	/// Record a sample, overwriting the oldest one once the buffer is full.
+	pub fn record(&self, duration: Duration) {
+		let slot = self.next.fetch_add(1, Ordering::Relaxed) % SAMPLE_COUNT;
+		// Zero marks an empty slot, so round sub-microsecond samples up.
+		let micros = u64::try_from(duration.as_micros())
+			.unwrap_or(u64::MAX)
+			.max(1);
+
+		self.samples[slot].store(micros, Ordering::Relaxed);
+	}
+
+	/// Start timing a database operation. Pass the returned callback to `FutureExt::inspect` on the query,
+	/// so its latency is recorded once it succeeds.
+	pub fn timer<T, E>(&self) -> impl FnOnce(&Result<T, E>) + '_ {
+		let start = Instant::now();
+
+		move |result| {
+			if result.is_ok() {
+				self.record(start.elapsed());
+			}
+		}
+	}
+
+	/// The given percentile (0-100) of the recorded samples, in microseconds.
+	pub fn percentile(&self, percentile: usize) -> Option<u64> {
+		let mut samples = self
+			.samples
+			.iter()
+			.map(|s| s.load(Ordering::Relaxed))
+			.filter(|&s| s > 0)
+			.collect::<Vec<_>>();
+
+		if samples.is_empty() {
+			return None;
+		}
+
+		samples.sort_unstable();
+		Some(samples[(samples.len() - 1) * percentile.min(100) / 100])
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn samples(micros: impl IntoIterator<Item = u64>) -> Samples {
+		let samples = Samples::default();
+		for micros in micros {
+			samples.record(Duration::from_micros(micros));
+		}
+		samples
+	}
+
+	#[test]
+	fn reports_nothing_without_samples() {
+		assert_eq!(Samples::default().percentile(50), None);
+		assert_eq!(Samples::default().percentile(95), None);
+	}
+
+	#[test]
+	fn reports_percentiles() {
+		let samples = samples(1..=100);
+
+		assert_eq!(samples.percentile(0), Some(1));
+		assert_eq!(samples.percentile(50), Some(50));
+		assert_eq!(samples.percentile(95), Some(95));
+		assert_eq!(samples.percentile(100), Some(100));
+		// Anything past 100 is treated as the maximum.
+		assert_eq!(samples.percentile(150), Some(100));
+	}
+
+	#[test]
+	fn keeps_only_the_latest_samples() {
+		// Fill the buffer with slow samples, then overwrite every one of them with fast ones.
+		let samples = samples([1000; SAMPLE_COUNT]);
+		for _ in 0..SAMPLE_COUNT {
+			samples.record(Duration::from_micros(10));
+		}
+
+		assert_eq!(samples.percentile(100), Some(10));
+	}
+
+	#[test]
+	fn rounds_sub_microsecond_samples_up() {
+		let samples = samples([0]);
+
+		assert_eq!(samples.percentile(50), Some(1));
+	}
+
+	#[test]
+	fn times_successful_operations_only() {
+		let samples = Samples::default();
+
+		(samples.timer())(&Err::<(), ()>(()));
+		assert_eq!(samples.percentile(50), None);
+
+		(samples.timer())(&Ok::<(), ()>(()));
+		assert!(samples.percentile(50).is_some_and(|micros| micros > 0));
+	}
+}
@@ -1,11 +1,15 @@
 #![warn(clippy::all, clippy::pedantic, clippy::nursery)]
 
+mod audit;
 mod blocklist;
 mod config;
+mod latency;
 mod routes;
 mod server;
+mod single_flight;
 mod types;
 mod utils;
+mod validation;
 mod verify;
 
 #[tokio::main]
@@ -1,7 +1,75 @@
 use aide::axum::ApiRouter;
+use tower_http::compression::CompressionLayer;
 
-mod v1;
+use crate::config::Config;
 
-pub fn handler() -> ApiRouter {
-	ApiRouter::new().nest("/v1", v1::handler())
+pub(crate) mod v1;
+
+pub fn handler(config: &Config) -> ApiRouter {
+	ApiRouter::new()
+		.nest("/v1", v1::handler(config))
+		.layer(compression())
+}
+
+/// Gzip responses for clients that advertise support, which matters most for large batch queries and search results.
+/// Images are already compressed, so they're sent as-is.
+fn compression() -> CompressionLayer {
+	CompressionLayer::new()
+}
+
+#[cfg(test)]
+mod tests {
+	use axum::{body::Body, response::IntoResponse, routing::get, Json, Router};
+	use http::{header, Request, Response};
+	use tower::ServiceExt;
+
+	use super::*;
+
+	async fn fetch(path: &str, accept_encoding: Option<&str>) -> Response<Body> {
+		let app = Router::new()
+			.route(
+				"/query",
+				get(|| async { Json(vec!["a-fairly-long-username-record"; 1000]) }),
+			)
+			.route(
+				"/avatar",
+				get(|| async {
+					([(header::CONTENT_TYPE, "image/png")], vec![0u8; 4096]).into_response()
+				}),
+			)
+			.layer(compression());
+
+		let mut request = Request::get(path);
+		if let Some(accept_encoding) = accept_encoding {
+			request = request.header(header::ACCEPT_ENCODING, accept_encoding);
+		}
+
+		app.oneshot(request.body(Body::empty()).unwrap())
+			.await
+			.unwrap()
+	}
+
+	#[tokio::test]
+	async fn gzips_large_json_when_accepted() {
+		let response = fetch("/query", Some("gzip")).await;
+
+		assert_eq!(
+			response.headers().get(header::CONTENT_ENCODING).unwrap(),
+			"gzip"
+		);
+	}
+
+	#[tokio::test]
+	async fn sends_json_as_is_without_accept_encoding() {
+		let response = fetch("/query", None).await;
+
+		assert!(response.headers().get(header::CONTENT_ENCODING).is_none());
+	}
+
+	#[tokio::test]
+	async fn sends_images_as_is() {
+		let response = fetch("/avatar", Some("gzip")).await;
+
+		assert!(response.headers().get(header::CONTENT_ENCODING).is_none());
+	}
 }
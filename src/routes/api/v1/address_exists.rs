@@ -0,0 +1,106 @@
+use std::{future::Future, str::FromStr};
+
+use alloy::primitives::Address;
+use axum::{
+	extract::Path,
+	response::{IntoResponse, Response},
+	Extension,
+};
+use axum_jsonschema::Json;
+use futures::FutureExt;
+use http::header;
+
+use crate::{
+	config::Db,
+	types::{AddressExistsResponse, ErrorResponse},
+};
+
+/// How long clients may cache the answer. Kept short, since it flips as soon as the address registers a username.
+const CACHE_MAX_AGE: u64 = 30;
+
+pub async fn address_exists(
+	Extension(db): Extension<Db>,
+	Path(address): Path<String>,
+) -> Result<Response, ErrorResponse> {
+	let response = check(&address, |address| async move {
+		let record = sqlx::query!(
+			"SELECT EXISTS(SELECT 1 FROM names WHERE address = $1) AS has_username",
+			address
+		)
+		.fetch_one(&db.read_only)
+		.inspect(db.latency.read_only.timer())
+		.await?;
+
+		Ok::<_, sqlx::Error>(record.has_username.unwrap_or_default())
+	})
+	.await?;
+
+	Ok((
+		[(
+			header::CACHE_CONTROL,
+			format!("public, max-age={CACHE_MAX_AGE}"),
+		)],
+		Json(response),
+	)
+		.into_response())
+}
+
+/// Check whether an address has a username, with `has_username` looking up its checksummed form.
+async fn check<F, Fut>(
+	address: &str,
+	has_username: F,
+) -> Result<AddressExistsResponse, ErrorResponse>
+where
+	F: FnOnce(String) -> Fut,
+	Fut: Future<Output = Result<bool, sqlx::Error>>,
+{
+	let address = Address::from_str(address)
+		.map_err(|_| ErrorResponse::validation_error("Invalid address".to_string()))?;
+
+	Ok(AddressExistsResponse {
+		exists: has_username(address.to_checksum(None)).await?,
+	})
+}
+
+pub fn docs(op: aide::transform::TransformOperation) -> aide::transform::TransformOperation {
+	op.description("Check whether an address has registered a World App username.")
+		.response::<200, Json<AddressExistsResponse>>()
+		.response::<422, ErrorResponse>()
+}
+
+#[cfg(test)]
+mod tests {
+	use http::StatusCode;
+
+	use super::*;
+
+	const REGISTERED: &str = "0x000000000000000000000000000000000000dEaD";
+
+	async fn exists(address: &str) -> Result<bool, ErrorResponse> {
+		check(address, |address| async move { Ok(address == REGISTERED) })
+			.await
+			.map(|response| response.exists)
+	}
+
+	#[tokio::test]
+	async fn address_with_a_username_exists() {
+		assert!(exists(REGISTERED).await.unwrap());
+		// Lookups use the checksummed address, however it was sent.
+		assert!(exists(&REGISTERED.to_lowercase()).await.unwrap());
+	}
+
+	#[tokio::test]
+	async fn address_without_a_username_does_not_exist() {
+		assert!(!exists("0x0000000000000000000000000000000000000001")
+			.await
+			.unwrap());
+	}
+
+	#[tokio::test]
+	async fn rejects_invalid_addresses() {
+		assert_eq!(
+			exists("0x1234").await.unwrap_err().status(),
+			StatusCode::UNPROCESSABLE_ENTITY
+		);
+	}
+}
@@ -0,0 +1,135 @@
+use std::collections::{HashMap, HashSet};
+
+use axum::Extension;
+use axum_jsonschema::Json;
+use futures::FutureExt;
+use idkit::session::VerificationLevel;
+
+use crate::{
+	blocklist::{Blocklist, BlocklistExt},
+	config::{ConfigExt, Db, DEVICE_USERNAME_REGEX},
+	types::{AvailabilityPayload, ErrorResponse, UsernameAvailability, ValidationFailure},
+	validation::username_failures,
+};
+
+pub async fn available(
+	Extension(config): ConfigExt,
+	Extension(db): Extension<Db>,
+	Extension(blocklist): BlocklistExt,
+	Json(payload): Json<AvailabilityPayload>,
+) -> Result<Json<HashMap<String, UsernameAvailability>>, ErrorResponse> {
+	if payload.usernames.len() > config.available_max_batch_size {
+		return Err(ErrorResponse::payload_too_large(format!(
+			"At most {} usernames can be checked at once",
+			config.available_max_batch_size
+		)));
+	}
+
+	let lowercase_usernames = payload
+		.usernames
+		.iter()
+		.map(|username| username.to_lowercase())
+		.collect::<Vec<_>>();
+
+	// Matches the register uniqueness check: taken case-insensitively, including renamed and deleted usernames.
+	let taken = sqlx::query_scalar!(
+		r#"SELECT LOWER(username) as "username!" FROM names WHERE LOWER(username) = ANY($1)
+		UNION
+		SELECT LOWER(old_username) FROM old_names WHERE LOWER(old_username) = ANY($1)
+		UNION
+		SELECT LOWER(username) FROM deleted_names WHERE LOWER(username) = ANY($1)"#,
+		&lowercase_usernames
+	)
+	.fetch_all(&db.read_only)
+	.inspect(db.latency.read_only.timer())
+	.await?
+	.into_iter()
+	.collect::<HashSet<_>>();
+
+	let verification_level = payload.verification_level.as_ref().map(|level| level.0);
+
+	Ok(Json(
+		payload
+			.usernames
+			.into_iter()
+			.map(|username| {
+				let availability = availability(&username, verification_level, &blocklist, &taken);
+
+				(username, availability)
+			})
+			.collect(),
+	))
+}
+
+/// Check a single username. Without a World ID, every reservation is treated as someone else's.
+fn availability(
+	username: &str,
+	verification_level: Option<VerificationLevel>,
+	blocklist: &Blocklist,
+	taken: &HashSet<String>,
+) -> UsernameAvailability {
+	let verification_level = verification_level.unwrap_or_else(|| {
+		if DEVICE_USERNAME_REGEX.is_match(username) {
+			VerificationLevel::Device
+		} else {
+			VerificationLevel::Orb
+		}
+	});
+
+	let reason = username_failures(username, &verification_level, "", blocklist)
+		.into_iter()
+		.next()
+		.or_else(|| {
+			taken
+				.contains(&username.to_lowercase())
+				.then(|| ValidationFailure::new("taken", "Username is already taken"))
+		});
+
+	UsernameAvailability {
+		available: reason.is_none(),
+		reason,
+	}
+}
+
+pub fn docs(op: aide::transform::TransformOperation) -> aide::transform::TransformOperation {
+	op.description("Check whether several usernames can be registered at once. Each username maps to its availability and, when unavailable, the first reason why (e.g. `taken` or `reserved`). Usernames are checked against the rules of the given verification level, or of the level their format implies.")
+		.response::<413, ErrorResponse>()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn reason(username: &str, verification_level: Option<VerificationLevel>) -> Option<String> {
+		let blocklist = Blocklist::new("admin", "badword", "");
+		let taken = HashSet::from(["bobby".to_string()]);
+
+		availability(username, verification_level, &blocklist, &taken)
+			.reason
+			.map(|failure| failure.code)
+	}
+
+	#[test]
+	fn checks_device_usernames_against_device_rules() {
+		assert_eq!(reason("alice.1234", None), None);
+		assert_eq!(reason("alice.1234", Some(VerificationLevel::Device)), None);
+		assert_eq!(
+			reason("alice.1234", Some(VerificationLevel::Orb)).as_deref(),
+			Some("invalid_characters")
+		);
+	}
+
+	#[test]
+	fn checks_other_usernames_against_orb_rules() {
+		assert_eq!(reason("alice", None), None);
+		assert_eq!(reason("abc", None).as_deref(), Some("too_short"));
+	}
+
+	#[test]
+	fn reports_taken_and_reserved_usernames() {
+		assert_eq!(reason("bobby1", None), None);
+		assert_eq!(reason("boBby", None).as_deref(), Some("taken"));
+		assert_eq!(reason("admin", None).as_deref(), Some("reserved"));
+		assert_eq!(reason("available", None).as_deref(), Some("reserved"));
+	}
+}
@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+
+use axum::Extension;
+use axum_jsonschema::Json;
+use futures::FutureExt;
+use url::Url;
+
+use crate::{
+	config::{ConfigExt, Db},
+	types::{ErrorResponse, QueryUsernamesPayload},
+};
+
+pub async fn avatars(
+	Extension(config): ConfigExt,
+	Extension(db): Extension<Db>,
+	Json(payload): Json<QueryUsernamesPayload>,
+) -> Result<Json<HashMap<String, Option<Url>>>, ErrorResponse> {
+	if payload.usernames.len() > config.avatars_max_batch_size {
+		return Err(ErrorResponse::payload_too_large(format!(
+			"At most {} usernames can be resolved at once",
+			config.avatars_max_batch_size
+		)));
+	}
+
+	let records = sqlx::query!(
+		"SELECT username, profile_picture_url FROM names WHERE username = ANY($1)",
+		&payload.usernames
+	)
+	.fetch_all(&db.read_only)
+	.inspect(db.latency.read_only.timer())
+	.await?;
+
+	Ok(Json(avatar_urls(
+		records
+			.into_iter()
+			.map(|r| (r.username, r.profile_picture_url)),
+	)))
+}
+
+/// Map each registered username to its profile picture, if it has a valid one.
+fn avatar_urls(
+	records: impl IntoIterator<Item = (String, Option<String>)>,
+) -> HashMap<String, Option<Url>> {
+	records
+		.into_iter()
+		.map(|(username, profile_picture_url)| {
+			let avatar_url = profile_picture_url.and_then(|url| url.parse().ok());
+			(username, avatar_url)
+		})
+		.collect()
+}
+
+pub fn docs(op: aide::transform::TransformOperation) -> aide::transform::TransformOperation {
+	op.description("Resolve the profile pictures of multiple usernames at once. Usernames without a profile picture map to null, and unregistered usernames are omitted.")
+		.response::<413, ErrorResponse>()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn maps_set_and_unset_avatars() {
+		let avatars = avatar_urls([
+			(
+				"alice".to_string(),
+				Some("https://static.world.org/alice.png".to_string()),
+			),
+			("bob".to_string(), None),
+			// A corrupt stored URL resolves like an unset one instead of failing the batch.
+			("carol".to_string(), Some("not a url".to_string())),
+		]);
+
+		assert_eq!(avatars.len(), 3);
+		assert_eq!(
+			avatars["alice"].as_ref().map(Url::as_str),
+			Some("https://static.world.org/alice.png")
+		);
+		assert_eq!(avatars["bob"], None);
+		assert_eq!(avatars["carol"], None);
+		// Unregistered usernames aren't in the database results, so they're omitted.
+		assert!(!avatars.contains_key("dave"));
+	}
+}
@@ -0,0 +1,39 @@
+use std::str::FromStr;
+
+use alloy::primitives::B256;
+use axum::{extract::Path, Extension};
+use axum_jsonschema::Json;
+use futures::FutureExt;
+
+use crate::{
+	config::Db,
+	types::{ErrorResponse, Name, UsernameRecord},
+};
+
+pub async fn by_node(
+	Extension(db): Extension<Db>,
+	Path(node): Path<String>,
+) -> Result<Json<UsernameRecord>, ErrorResponse> {
+	let node = B256::from_str(&node)
+		.map_err(|_| ErrorResponse::validation_error("Invalid node".to_string()))?;
+
+	let Some(name) = sqlx::query_as!(
+		Name,
+		"SELECT * FROM names WHERE username = (SELECT username FROM name_nodes WHERE node = $1)",
+		node.as_slice()
+	)
+	.fetch_optional(&db.read_only)
+	.inspect(db.latency.read_only.timer())
+	.await?
+	else {
+		return Err(ErrorResponse::not_found("Record not found.".to_string()));
+	};
+
+	Ok(Json(UsernameRecord::from(name)))
+}
+
+pub fn docs(op: aide::transform::TransformOperation) -> aide::transform::TransformOperation {
+	op.description("Resolve a username from its ENS namehash (node) under any configured ENS domain. Requires the system API key.")
+		.response::<404, ErrorResponse>()
+		.response::<422, ErrorResponse>()
+}
@@ -1,5 +1,5 @@
 use alloy::{
-	primitives::{keccak256, Address, U64},
+	primitives::{keccak256, Address, Bytes as AbiBytes, B256, U64},
 	signers::{local::PrivateKeySigner, Signature, Signer},
 	sol_types::{SolCall, SolValue},
 };
@@ -9,13 +9,14 @@ use axum::{
 };
 use axum_jsonschema::Json;
 use chrono::{TimeDelta, Utc};
+use futures::FutureExt;
 use serde_json::from_slice;
 use std::{str::FromStr, sync::Arc};
 
 use crate::{
 	config::{Config, ConfigExt, Db},
 	types::{ENSErrorResponse, ENSQueryPayload, ENSResponse, Method, Name, ResolveRequest},
-	utils::namehash,
+	utils::{namehash, normalize_name},
 };
 
 pub async fn ens_gateway_post(
@@ -23,15 +24,11 @@ pub async fn ens_gateway_post(
 	Extension(db): Extension<Db>,
 	body: Bytes, // Accept the raw request body as Bytes
 ) -> Result<Json<ENSResponse>, ENSErrorResponse> {
-	// TODO: Remove these after figuring out what ENS is failing on
-	let request_payload: ENSQueryPayload = match from_slice(&body) {
-		Ok(payload) => payload, // Successfully parsed
-		Err(_) => {
-			// Return an error response if JSON parsing fails
-			return Err(ENSErrorResponse::new("Failed to parse JSON payload."));
-		},
-	};
-	process_ens_request(config, db, request_payload).await
+	let classify = config.ens_error_classification;
+	let request_payload = parse_payload(&body).map_err(|e| e.classified(classify))?;
+	process_ens_request(config, db, request_payload)
+		.await
+		.map_err(|e| e.classified(classify))
 }
 
 pub async fn ens_gateway_get(
@@ -39,16 +36,19 @@ pub async fn ens_gateway_get(
 	Extension(db): Extension<Db>,
 	Path((sender, data)): Path<(String, String)>,
 ) -> Result<Json<ENSResponse>, ENSErrorResponse> {
-	let sender_address = crate::types::Address(
-		Address::from_str(&sender).map_err(|_| ENSErrorResponse::new("Invalid sender address."))?,
-	);
+	let classify = config.ens_error_classification;
+	let sender_address = parse_sender(&sender).map_err(|e| e.classified(classify))?;
+
+	validate_data_param(&data).map_err(|e| e.classified(classify))?;
 
 	let request_payload = ENSQueryPayload {
 		sender: sender_address,
 		data,
 	};
 
-	process_ens_request(config, db, request_payload).await
+	process_ens_request(config, db, request_payload)
+		.await
+		.map_err(|e| e.classified(classify))
 }
 
 async fn process_ens_request(
@@ -56,69 +56,189 @@ async fn process_ens_request(
 	db: Db,
 	request_payload: ENSQueryPayload,
 ) -> Result<Json<ENSResponse>, ENSErrorResponse> {
-	let (req_data, name, method) = decode_payload(&request_payload)
-		.map_err(|_| ENSErrorResponse::new("Failed to decode payload."))?;
+	let (req_data, name, method) = decode_request(&request_payload)?;
 
-	let username = name
-		.strip_suffix(&format!(".{}", config.ens_domain))
-		.ok_or_else(|| ENSErrorResponse::new("Name not found."))?;
+	// Wallets may send zero-width characters or whitespace; only the lookup ignores them, the node check doesn't.
+	let normalized_name = normalize_name(&name);
+	let username = username_for(&config, &normalized_name)?;
 
 	let record = sqlx::query_as!(Name, "SELECT * FROM names WHERE username = $1", username)
-		.fetch_one(&db.read_only)
+		.fetch_optional(&db.read_only)
+		.inspect(db.latency.read_only.timer())
 		.await
-		.map_err(|_| ENSErrorResponse::new("Name not found."))?;
+		.map_err(|_| ENSErrorResponse::transient("lookup_failed", "Failed to look up name."))?
+		.ok_or_else(|| ENSErrorResponse::new("name_not_found", "Name not found."))?;
+
+	let result = resolve_method(&config, &name, &record, method)?;
 
-	let result: Vec<u8> = match method {
+	sign_response(config, result, &req_data, request_payload.sender)
+		.await
+		.map(|data| Json(ENSResponse { data }))
+		.map_err(|_| ENSErrorResponse::transient("signing_failed", "Failed to sign response."))
+}
+
+pub fn docs(op: aide::transform::TransformOperation) -> aide::transform::TransformOperation {
+	op.description("CCIP Read Gateway powering the ENS integration.")
+}
+
+/// ABI-encode what a method resolves to for a record, as the gateway answers it before signing.
+pub(crate) fn resolve_method(
+	config: &Config,
+	name: &str,
+	record: &Name,
+	method: Method,
+) -> Result<Vec<u8>, ENSErrorResponse> {
+	let result = match method {
 		Method::Text(node, key) => {
-			if node != namehash(&name) {
-				return Err(ENSErrorResponse::new("Invalid node hash provided."));
-			}
+			ensure_node(&node, name)?;
 
 			match key.as_str() {
 				"avatar" => {
-					let Some(avatar_url) = record.profile_picture_url else {
-						return Err(ENSErrorResponse::new(&format!("Record not found: {key}")));
+					let Some(avatar_url) = &record.profile_picture_url else {
+						return Err(ENSErrorResponse::new(
+							"record_not_found",
+							&format!("Record not found: {key}"),
+						));
 					};
 
-					(avatar_url).abi_encode()
+					avatar_url.abi_encode()
+				},
+				"org.worldcoin.level" if config.ens_verification_level_record => {
+					record.verification_level.abi_encode()
+				},
+				// Keys some resolvers probe for (e.g. etherscan's email/url) resolve to their configured default.
+				_ => match config.ens_default_text_records.get(&key) {
+					Some(value) => value.abi_encode(),
+					None => {
+						return Err(ENSErrorResponse::new(
+							"record_not_found",
+							&format!("Record not found: {key}"),
+						))
+					},
 				},
-				// hack to hide etherscan error
-				"email" => "".to_string().abi_encode(),
-				"url" => "".to_string().abi_encode(),
-
-				// Support for other might be implemented in the future.
-				_ => return Err(ENSErrorResponse::new(&format!("Record not found: {key}"))),
 			}
 		},
 		Method::Addr(node) => {
-			if node != namehash(&name) {
-				return Err(ENSErrorResponse::new("Invalid node hash provided."));
-			}
+			ensure_node(&node, name)?;
+
+			// A corrupt row (e.g. from a bad import) must fail this lookup, not take down the gateway.
+			let address = Address::parse_checksummed(&record.address, None).map_err(|e| {
+				tracing::error!(
+					"ENS Gateway Error: invalid stored address {:?} for {}: {e}",
+					record.address,
+					record.username
+				);
+				ENSErrorResponse::transient("invalid_record", "Failed to resolve address.")
+			})?;
+
+			address.abi_encode()
+		},
+		Method::ContentHash(node) => {
+			ensure_node(&node, name)?;
+
+			encode_contenthash(record.contenthash.clone())
+		},
+		Method::PubKey(node) => {
+			ensure_node(&node, name)?;
 
-			(Address::parse_checksummed(record.address, None).unwrap()).abi_encode()
+			encode_pubkey(record.pubkey_x.clone(), record.pubkey_y.clone())
 		},
 		Method::AddrMultichain | Method::Name => {
-			return Err(ENSErrorResponse::new("Not implemented."));
+			return Err(ENSErrorResponse::new(
+				"unsupported_method",
+				"Not implemented.",
+			));
 		},
 		_ => ().abi_encode(),
 	};
 
-	sign_response(config, result, &req_data, request_payload.sender)
-		.await
-		.map(|data| Json(ENSResponse { data }))
-		.map_err(|_| ENSErrorResponse::new("Failed to sign response."))
+	Ok(result)
 }
 
-pub fn docs(op: aide::transform::TransformOperation) -> aide::transform::TransformOperation {
-	op.description("CCIP Read Gateway powering the ENS integration.")
+/// The username a normalized name resolves to, if it's under one of the configured domains.
+fn username_for<'a>(config: &Config, name: &'a str) -> Result<&'a str, ENSErrorResponse> {
+	config
+		.strip_ens_domain(name)
+		.ok_or_else(|| ENSErrorResponse::new("name_not_found", "Name not found."))
 }
 
-fn decode_payload(payload: &ENSQueryPayload) -> Result<(Vec<u8>, String, Method), anyhow::Error> {
-	let data = if payload.data.ends_with(".json") {
-		&payload.data[2..payload.data.len() - 5]
-	} else {
-		&payload.data[2..]
+/// Ensure the node a method was called with is the namehash of the requested name, as the resolver computed it.
+fn ensure_node(node: &[u8], name: &str) -> Result<(), ENSErrorResponse> {
+	if node != namehash(name) {
+		return Err(ENSErrorResponse::new(
+			"invalid_node",
+			"Invalid node hash provided.",
+		));
+	}
+
+	Ok(())
+}
+
+/// ABI-encode stored public key coordinates as `(bytes32, bytes32)`. Unset keys resolve to (0, 0), as the public resolver does.
+fn encode_pubkey(x: Option<Vec<u8>>, y: Option<Vec<u8>>) -> Vec<u8> {
+	let coordinate = |c: Option<Vec<u8>>| {
+		c.filter(|c| c.len() == 32)
+			.map_or(B256::ZERO, |c| B256::from_slice(&c))
 	};
+
+	(coordinate(x), coordinate(y)).abi_encode()
+}
+
+/// ABI-encode a stored content hash as `bytes`, resolving unset ones to empty bytes as the public resolver does.
+fn encode_contenthash(contenthash: Option<Vec<u8>>) -> Vec<u8> {
+	AbiBytes::from(contenthash.unwrap_or_default()).abi_encode()
+}
+
+/// Ensure the `data` path segment is 0x-prefixed, even-length hex (optionally `.json`-suffixed).
+fn validate_data_param(data: &str) -> Result<(), ENSErrorResponse> {
+	let hex_data = data
+		.strip_suffix(".json")
+		.unwrap_or(data)
+		.strip_prefix("0x")
+		.ok_or_else(|| ENSErrorResponse::new("invalid_data", "Data must be 0x-prefixed."))?;
+
+	if hex_data.len() % 2 != 0 {
+		return Err(ENSErrorResponse::new(
+			"invalid_data",
+			"Data must have an even length.",
+		));
+	}
+
+	if !hex_data.bytes().all(|b| b.is_ascii_hexdigit()) {
+		return Err(ENSErrorResponse::new(
+			"invalid_data",
+			"Data must be hex-encoded.",
+		));
+	}
+
+	Ok(())
+}
+
+fn parse_payload(body: &[u8]) -> Result<ENSQueryPayload, ENSErrorResponse> {
+	from_slice(body)
+		.map_err(|_| ENSErrorResponse::new("invalid_payload", "Failed to parse JSON payload."))
+}
+
+fn parse_sender(sender: &str) -> Result<crate::types::Address, ENSErrorResponse> {
+	Address::from_str(sender)
+		.map(crate::types::Address)
+		.map_err(|_| ENSErrorResponse::new("invalid_sender", "Invalid sender address."))
+}
+
+fn decode_request(
+	payload: &ENSQueryPayload,
+) -> Result<(Vec<u8>, String, Method), ENSErrorResponse> {
+	decode_payload(payload)
+		.map_err(|_| ENSErrorResponse::new("decode_error", "Failed to decode payload."))
+}
+
+fn decode_payload(payload: &ENSQueryPayload) -> Result<(Vec<u8>, String, Method), anyhow::Error> {
+	let data = payload
+		.data
+		.strip_suffix(".json")
+		.unwrap_or(&payload.data)
+		.strip_prefix("0x")
+		.ok_or_else(|| anyhow::anyhow!("data is not 0x-prefixed"))?;
 	let req_data = hex::decode(data)?;
 	let decoded_req = ResolveRequest::abi_decode(&req_data, true)?;
 
@@ -158,3 +278,338 @@ async fn sign_response(
 		hex::encode((response, expires_at, signature.as_bytes().to_vec()).abi_encode_params())
 	))
 }
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashMap;
+
+	use super::*;
+
+	#[test]
+	fn checks_the_node_against_the_name_as_sent() {
+		let name = "ali\u{200b}ce.world.id";
+
+		assert!(ensure_node(&namehash(name), name).is_ok());
+		assert!(ensure_node(&namehash("bob.world.id"), name).is_err());
+	}
+
+	#[test]
+	fn zero_width_names_look_up_the_clean_username() {
+		let name = "ali\u{200b}ce.world.id";
+
+		assert_eq!(normalize_name(name), "alice.world.id");
+		// A resolver hashes the name as typed, which isn't the clean name's node.
+		assert_ne!(namehash(name), namehash("alice.world.id"));
+	}
+
+	#[test]
+	fn encodes_stored_contenthash() {
+		let contenthash = hex::decode(
+			"e3010170122029f2d17be6139079dc48696d1f582a8530eb9805b561eda517e22a892c7e3f1f",
+		)
+		.unwrap();
+
+		let encoded = encode_contenthash(Some(contenthash.clone()));
+
+		assert_eq!(
+			AbiBytes::abi_decode(&encoded, true).unwrap().to_vec(),
+			contenthash
+		);
+	}
+
+	#[test]
+	fn encodes_unset_contenthash_as_empty_bytes() {
+		let encoded = encode_contenthash(None);
+
+		assert!(AbiBytes::abi_decode(&encoded, true).unwrap().is_empty());
+		assert_eq!(encoded, AbiBytes::new().abi_encode());
+	}
+
+	#[test]
+	fn encodes_stored_pubkey() {
+		let (x, y) = (B256::repeat_byte(0x11), B256::repeat_byte(0x22));
+
+		let encoded = encode_pubkey(Some(x.to_vec()), Some(y.to_vec()));
+
+		assert_eq!(<(B256, B256)>::abi_decode(&encoded, true).unwrap(), (x, y));
+	}
+
+	#[test]
+	fn encodes_unset_pubkey_as_zero() {
+		let zero = (B256::ZERO, B256::ZERO);
+
+		assert_eq!(
+			<(B256, B256)>::abi_decode(&encode_pubkey(None, None), true).unwrap(),
+			zero
+		);
+		// Coordinates that aren't 32 bytes can't be a valid key either.
+		assert_eq!(
+			<(B256, B256)>::abi_decode(&encode_pubkey(Some(vec![1]), None), true).unwrap(),
+			zero
+		);
+	}
+
+	fn record() -> Name {
+		Name {
+			address: "0x000000000000000000000000000000000000dEaD".to_string(),
+			username: "alice".to_string(),
+			profile_picture_url: None,
+			nullifier_hash: "0x1".to_string(),
+			verification_level: "orb".to_string(),
+			created_at: Utc::now().naive_utc(),
+			updated_at: Utc::now().naive_utc(),
+			contenthash: None,
+			pubkey_x: None,
+			pubkey_y: None,
+		}
+	}
+
+	fn payload(data: &str) -> ENSQueryPayload {
+		ENSQueryPayload {
+			data: data.to_string(),
+			sender: parse_sender("0x000000000000000000000000000000000000dEaD").unwrap(),
+		}
+	}
+
+	fn code<T>(result: Result<T, ENSErrorResponse>) -> Option<String> {
+		result.err().and_then(|e| e.code)
+	}
+
+	#[test]
+	fn maps_each_error_to_its_code() {
+		let config = Config::for_tests();
+		let name = "alice.world.id";
+		let node = namehash(name).to_vec();
+		let (record, corrupt) = (
+			record(),
+			Name {
+				address: "not an address".to_string(),
+				..record()
+			},
+		);
+		let resolve = |record: &Name, method| resolve_method(&config, name, record, method);
+
+		let cases = [
+			(code(parse_payload(b"not json")), "invalid_payload"),
+			(code(parse_sender("0x1234")), "invalid_sender"),
+			(code(validate_data_param("9061b923")), "invalid_data"),
+			(code(decode_request(&payload("0xdeadbeef"))), "decode_error"),
+			(code(username_for(&config, "alice.eth")), "name_not_found"),
+			(
+				code(resolve(
+					&record,
+					Method::Addr(namehash("bob.world.id").to_vec()),
+				)),
+				"invalid_node",
+			),
+			(
+				code(resolve(
+					&record,
+					Method::Text(node.clone(), "avatar".to_string()),
+				)),
+				"record_not_found",
+			),
+			(
+				code(resolve(
+					&record,
+					Method::Text(node.clone(), "com.twitter".to_string()),
+				)),
+				"record_not_found",
+			),
+			(code(resolve(&record, Method::Name)), "unsupported_method"),
+			(
+				code(resolve(&record, Method::AddrMultichain)),
+				"unsupported_method",
+			),
+			(
+				code(resolve(&corrupt, Method::Addr(node))),
+				"invalid_record",
+			),
+		];
+
+		for (i, (actual, expected)) in cases.into_iter().enumerate() {
+			assert_eq!(actual.as_deref(), Some(expected), "case {i}");
+		}
+	}
+
+	#[test]
+	fn corrupt_stored_addresses_fail_cleanly() {
+		let config = Config::for_tests();
+		let name = "alice.world.id";
+
+		for address in ["not an address", "", "0x1234"] {
+			let record = Name {
+				address: address.to_string(),
+				..record()
+			};
+
+			let error = resolve_method(
+				&config,
+				name,
+				&record,
+				Method::Addr(namehash(name).to_vec()),
+			)
+			.unwrap_err();
+
+			assert_eq!(error.code.as_deref(), Some("invalid_record"), "{address:?}");
+			// The stored address is only logged, not sent to the caller.
+			assert_eq!(error.message, "Failed to resolve address.");
+		}
+	}
+
+	#[test]
+	fn resolves_valid_requests() {
+		let config = Config::for_tests();
+		let name = "alice.world.id";
+		let node = namehash(name).to_vec();
+
+		assert_eq!(username_for(&config, name).unwrap(), "alice");
+		assert!(resolve_method(&config, name, &record(), Method::Addr(node.clone())).is_ok());
+		// Default text records resolve even though the name doesn't store them.
+		assert!(resolve_method(
+			&config,
+			name,
+			&record(),
+			Method::Text(node, "email".to_string())
+		)
+		.is_ok());
+	}
+
+	fn text(config: &Config, record: &Name, key: &str) -> Result<String, Option<String>> {
+		let name = format!("{}.world.id", record.username);
+		let method = Method::Text(namehash(&name).to_vec(), key.to_string());
+
+		resolve_method(config, &name, record, method)
+			.map(|encoded| String::abi_decode(&encoded, true).unwrap())
+			.map_err(|e| e.code)
+	}
+
+	#[test]
+	fn resolves_the_verification_level_record() {
+		let config = Config::for_tests();
+		let device = Name {
+			verification_level: "device".to_string(),
+			..record()
+		};
+
+		assert_eq!(
+			text(&config, &record(), "org.worldcoin.level").as_deref(),
+			Ok("orb")
+		);
+		assert_eq!(
+			text(&config, &device, "org.worldcoin.level").as_deref(),
+			Ok("device")
+		);
+	}
+
+	#[test]
+	fn verification_level_record_checks_the_node() {
+		let config = Config::for_tests();
+		let method = Method::Text(
+			namehash("bob.world.id").to_vec(),
+			"org.worldcoin.level".to_string(),
+		);
+
+		assert_eq!(
+			code(resolve_method(&config, "alice.world.id", &record(), method)).as_deref(),
+			Some("invalid_node")
+		);
+	}
+
+	#[test]
+	fn verification_level_record_can_be_disabled() {
+		let mut config = Config::for_tests();
+		config.ens_verification_level_record = false;
+
+		assert_eq!(
+			text(&config, &record(), "org.worldcoin.level"),
+			Err(Some("record_not_found".to_string()))
+		);
+	}
+
+	#[test]
+	fn unknown_names_resolve_no_level() {
+		let config = Config::for_tests();
+
+		// Names outside the configured domains never reach a record lookup.
+		assert_eq!(
+			code(username_for(&config, "alice.eth")).as_deref(),
+			Some("name_not_found")
+		);
+	}
+
+	#[test]
+	fn default_text_records_resolve_to_their_value() {
+		let mut config = Config::for_tests();
+		config.ens_default_text_records = HashMap::from([
+			("email".to_string(), String::new()),
+			("url".to_string(), "https://world.org".to_string()),
+		]);
+
+		assert_eq!(text(&config, &record(), "email").as_deref(), Ok(""));
+		assert_eq!(
+			text(&config, &record(), "url").as_deref(),
+			Ok("https://world.org")
+		);
+	}
+
+	#[test]
+	fn unsupported_text_records_are_not_found() {
+		let config = Config::for_tests();
+
+		assert_eq!(
+			text(&config, &record(), "com.twitter"),
+			Err(Some("record_not_found".to_string()))
+		);
+		// The avatar record errors when unset rather than falling back to a default.
+		assert_eq!(
+			text(&config, &record(), "avatar"),
+			Err(Some("record_not_found".to_string()))
+		);
+	}
+
+	fn data_error(data: &str) -> Option<String> {
+		validate_data_param(data).err().map(|e| e.message)
+	}
+
+	#[test]
+	fn accepts_hex_data() {
+		assert_eq!(data_error("0x9061b923"), None);
+		assert_eq!(data_error("0x9061B923"), None);
+		assert_eq!(data_error("0x9061b923.json"), None);
+		assert_eq!(data_error("0x"), None);
+	}
+
+	#[test]
+	fn rejects_data_without_0x_prefix() {
+		assert_eq!(
+			data_error("9061b923").as_deref(),
+			Some("Data must be 0x-prefixed.")
+		);
+		assert_eq!(
+			data_error("9061b923.json").as_deref(),
+			Some("Data must be 0x-prefixed.")
+		);
+	}
+
+	#[test]
+	fn rejects_odd_length_data() {
+		assert_eq!(
+			data_error("0x9061b92").as_deref(),
+			Some("Data must have an even length.")
+		);
+	}
+
+	#[test]
+	fn rejects_non_hex_data() {
+		assert_eq!(
+			data_error("0x9061zz23").as_deref(),
+			Some("Data must be hex-encoded.")
+		);
+		// Only a `.json` suffix is stripped, any other suffix is part of the data.
+		assert_eq!(
+			data_error("0x9061b923.txt").as_deref(),
+			Some("Data must be hex-encoded.")
+		);
+	}
+}
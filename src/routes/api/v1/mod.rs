@@ -2,44 +2,80 @@ use aide::axum::{
 	routing::{get_with, post_with},
 	ApiRouter,
 };
+use axum::{middleware, Extension};
 
-mod ens_gateway;
+mod address_exists;
+mod available;
+mod avatars;
+mod by_node;
+pub(crate) mod ens_gateway;
 mod query_multiple;
-mod query_single;
+pub(crate) mod query_single;
 mod register_username;
 mod rename;
+mod resolve;
 mod search;
 mod update_record;
 
+use address_exists::{address_exists, docs as address_exists_docs};
+use available::{available, docs as available_docs};
+use avatars::{avatars, docs as avatars_docs};
+use by_node::{by_node, docs as by_node_docs};
 use ens_gateway::{docs as ens_gateway_docs, ens_gateway_get, ens_gateway_post};
-use http::Method;
+use http::{
+	header::{CONTENT_TYPE, ETAG},
+	HeaderName, HeaderValue, Method,
+};
 use query_multiple::{docs as query_multiple_docs, query_multiple};
-use query_single::{docs as query_single_docs, query_single};
+use query_single::{docs as query_single_docs, query_single, LookupFlight};
 use register_username::{docs as register_username_docs, register_username};
 use rename::{docs as rename_docs, rename};
+use resolve::{docs as resolve_docs, resolve};
 use search::{docs as search_docs, search};
-use tower_http::cors::{Any, CorsLayer};
+use std::{sync::Arc, time::Duration};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
 use update_record::{docs as update_record_docs, update_record};
 
-pub fn handler() -> ApiRouter {
-	let cors = CorsLayer::new()
-	.allow_origin(Any) // Or you can specify allowed origins
-	.allow_methods(vec![Method::GET, Method::POST, Method::OPTIONS]) // Allow OPTIONS method
-	.allow_headers(Any); // Allow any headers
+use crate::{config::Config, routes::system::auth::system_auth, server::API_VERSION_HEADER};
 
-	ApiRouter::new()
+pub fn handler(config: &Config) -> ApiRouter {
+	let cors = cors_layer(
+		config.cors_allowed_origins.as_deref(),
+		config.cors_allowed_headers.clone(),
+		config.cors_max_age,
+	);
+
+	// CCIP-Read requests come from whichever dApp is resolving the name, so the gateway can't be origin-restricted.
+	let ens_gateway = ApiRouter::new()
 		.api_route("/ens", post_with(ens_gateway_post, ens_gateway_docs))
-		.layer(cors.clone())
 		.api_route("/ens/", post_with(ens_gateway_post, ens_gateway_docs))
-		.layer(cors.clone())
 		.api_route(
 			"/ens/:sender/:data",
 			get_with(ens_gateway_get, ens_gateway_docs),
 		)
-		.layer(cors.clone())
+		.layer(
+			CorsLayer::new()
+				.allow_origin(Any)
+				.allow_methods(vec![Method::GET, Method::POST, Method::OPTIONS])
+				.allow_headers([CONTENT_TYPE])
+				.max_age(config.cors_max_age),
+		);
+
+	// Lookups for internal tooling, not meant for browsers, so they sit behind the system API key instead of CORS.
+	let internal = ApiRouter::new()
+		.api_route("/internal/by-node/:node", get_with(by_node, by_node_docs))
+		.route_layer(middleware::from_fn(system_auth));
+
+	ApiRouter::new()
+		.api_route(
+			"/address/:address/exists",
+			get_with(address_exists, address_exists_docs),
+		)
+		.api_route("/available", post_with(available, available_docs))
+		.api_route("/avatars", post_with(avatars, avatars_docs))
 		.api_route("/query", post_with(query_multiple, query_multiple_docs))
-		.layer(cors.clone())
 		.api_route("/rename", post_with(rename, rename_docs))
+		.api_route("/resolve/:name", get_with(resolve, resolve_docs))
 		.api_route(
 			"/register",
 			post_with(register_username, register_username_docs),
@@ -48,10 +84,147 @@ pub fn handler() -> ApiRouter {
 			"/:name",
 			get_with(query_single, query_single_docs)
 				.post_with(update_record, update_record_docs)
-				.layer(cors.clone()),
-		)
-		.api_route(
-			"/search/:username",
-			get_with(search, search_docs).layer(cors),
+				.layer(Extension(Arc::new(LookupFlight::default()))),
 		)
+		.api_route("/search/:username", get_with(search, search_docs))
+		.layer(cors)
+		.merge(ens_gateway)
+		.merge(internal)
+}
+
+fn cors_layer(
+	origins: Option<&[HeaderValue]>,
+	allowed_headers: Vec<HeaderName>,
+	max_age: Duration,
+) -> CorsLayer {
+	CorsLayer::new()
+		.allow_origin(allow_origin(origins))
+		.allow_methods(vec![Method::GET, Method::POST, Method::OPTIONS])
+		.allow_headers(allowed_headers)
+		// Let browser clients read the caching and versioning headers.
+		.expose_headers([ETAG, API_VERSION_HEADER])
+		.max_age(max_age) // Let browsers cache preflight responses
+}
+
+/// The origins allowed to call the API cross-origin: the configured list, or any origin when none is configured (development only, see `Config`).
+fn allow_origin(origins: Option<&[HeaderValue]>) -> AllowOrigin {
+	origins.map_or_else(AllowOrigin::any, |origins| {
+		AllowOrigin::list(origins.to_vec())
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use axum::{body::Body, routing::get, Router};
+	use http::{header, Request};
+	use tower::ServiceExt;
+
+	use super::*;
+
+	async fn allowed_origin(allow_origin: AllowOrigin, origin: &str) -> Option<HeaderValue> {
+		let app = Router::new()
+			.route("/", get(|| async { "ok" }))
+			.layer(CorsLayer::new().allow_origin(allow_origin));
+
+		let response = app
+			.oneshot(
+				Request::get("/")
+					.header(header::ORIGIN, origin)
+					.body(Body::empty())
+					.unwrap(),
+			)
+			.await
+			.unwrap();
+
+		response
+			.headers()
+			.get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+			.cloned()
+	}
+
+	#[tokio::test]
+	async fn allows_only_configured_origins() {
+		let origins = [HeaderValue::from_static("https://world.org")];
+
+		assert_eq!(
+			allowed_origin(allow_origin(Some(origins.as_slice())), "https://world.org").await,
+			Some(HeaderValue::from_static("https://world.org"))
+		);
+		assert_eq!(
+			allowed_origin(
+				allow_origin(Some(origins.as_slice())),
+				"https://evil.example"
+			)
+			.await,
+			None
+		);
+	}
+
+	#[tokio::test]
+	async fn no_configured_origins_allows_any() {
+		assert_eq!(
+			allowed_origin(allow_origin(None), "https://localhost:3000").await,
+			Some(HeaderValue::from_static("*"))
+		);
+	}
+
+	#[tokio::test]
+	async fn exposes_caching_and_version_headers() {
+		let origins = [HeaderValue::from_static("https://world.org")];
+		let app = Router::new()
+			.route("/", get(|| async { "ok" }))
+			.layer(cors_layer(
+				Some(origins.as_slice()),
+				vec![CONTENT_TYPE],
+				Duration::from_secs(60),
+			));
+
+		let response = app
+			.oneshot(
+				Request::get("/")
+					.header(header::ORIGIN, "https://world.org")
+					.body(Body::empty())
+					.unwrap(),
+			)
+			.await
+			.unwrap();
+
+		let exposed = response
+			.headers()
+			.get(header::ACCESS_CONTROL_EXPOSE_HEADERS)
+			.unwrap()
+			.to_str()
+			.unwrap()
+			.to_lowercase();
+		assert!(exposed.contains("etag"));
+		assert!(exposed.contains("x-api-version"));
+	}
+
+	#[tokio::test]
+	async fn preflight_responses_are_cacheable() {
+		let origins = [HeaderValue::from_static("https://world.org")];
+		let app = Router::new()
+			.route("/", get(|| async { "ok" }))
+			.layer(cors_layer(
+				Some(origins.as_slice()),
+				vec![CONTENT_TYPE],
+				Duration::from_secs(600),
+			));
+
+		let response = app
+			.oneshot(
+				Request::options("/")
+					.header(header::ORIGIN, "https://world.org")
+					.header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+					.body(Body::empty())
+					.unwrap(),
+			)
+			.await
+			.unwrap();
+
+		assert_eq!(
+			response.headers().get(header::ACCESS_CONTROL_MAX_AGE),
+			Some(&HeaderValue::from_static("600"))
+		);
+	}
 }
@@ -1,32 +1,168 @@
 use axum::Extension;
 use axum_jsonschema::Json;
+use futures::FutureExt;
 
 use crate::{
-	config::Db,
+	config::{ConfigExt, Db},
 	types::{ErrorResponse, Name, QueryAddressesPayload, UsernameRecord},
 };
 
 pub async fn query_multiple(
+	Extension(config): ConfigExt,
 	Extension(db): Extension<Db>,
 	Json(payload): Json<QueryAddressesPayload>,
 ) -> Result<Json<Vec<UsernameRecord>>, ErrorResponse> {
+	ensure_not_empty(&payload, config.reject_empty_queries)?;
+
 	let addresses = payload
 		.addresses
 		.iter()
 		.map(|a| a.0.to_checksum(None))
 		.collect::<Vec<_>>();
 
+	// Fetch one past the limit so we can tell an exact fit from an overflow.
 	let names = sqlx::query_as!(
 		Name,
-		"SELECT * FROM names WHERE address = ANY($1)",
-		&addresses
+		"SELECT * FROM names WHERE address = ANY($1) LIMIT $2",
+		&addresses,
+		i64::try_from(config.query_multiple_max_results + 1).unwrap_or(i64::MAX)
 	)
 	.fetch_all(&db.read_only)
+	.inspect(db.latency.read_only.timer())
 	.await?;
 
-	Ok(Json(names.into_iter().map(UsernameRecord::from).collect()))
+	ensure_within_limit(names.len(), config.query_multiple_max_results)?;
+
+	Ok(Json(
+		names
+			.into_iter()
+			.map(|name| record(name, payload.include_timestamps))
+			.collect(),
+	))
+}
+
+/// Reject a request with no addresses, if configured to, so clients can tell an accidental empty request from no matches.
+fn ensure_not_empty(payload: &QueryAddressesPayload, reject: bool) -> Result<(), ErrorResponse> {
+	if payload.addresses.is_empty() && reject {
+		return Err(ErrorResponse::bad_request(
+			"At least one address must be provided".to_string(),
+		));
+	}
+
+	Ok(())
+}
+
+/// Refuse to send more than `max_results` records, rather than serialize an enormous response.
+fn ensure_within_limit(matches: usize, max_results: usize) -> Result<(), ErrorResponse> {
+	if matches > max_results {
+		return Err(ErrorResponse::payload_too_large(format!(
+			"Query matches more than {max_results} records"
+		)));
+	}
+
+	Ok(())
+}
+
+/// The record returned for a name, with when it was last updated only if requested.
+fn record(name: Name, include_timestamps: bool) -> UsernameRecord {
+	let updated_at = include_timestamps.then_some(name.updated_at);
+
+	UsernameRecord {
+		updated_at,
+		..UsernameRecord::from(name)
+	}
 }
 
 pub fn docs(op: aide::transform::TransformOperation) -> aide::transform::TransformOperation {
-	op.description("Resolve multiple addresses into their registered usernames.")
+	op.description("Resolve multiple addresses into their registered usernames. Addresses without a username are omitted, so a request with no matches returns an empty array. Depending on server configuration, a request with no addresses is either rejected with a 400 or also returns an empty array.")
+		.response::<400, ErrorResponse>()
+		.response::<413, ErrorResponse>()
+}
+
+#[cfg(test)]
+mod tests {
+	use http::StatusCode;
+
+	use super::*;
+	use crate::config::Config;
+
+	fn name() -> Name {
+		Name {
+			address: "0x000000000000000000000000000000000000dEaD".to_string(),
+			username: "alice".to_string(),
+			profile_picture_url: None,
+			nullifier_hash: "0x1".to_string(),
+			verification_level: "orb".to_string(),
+			created_at: chrono::Utc::now().naive_utc(),
+			updated_at: chrono::DateTime::from_timestamp(1_700_000_000, 0)
+				.unwrap()
+				.naive_utc(),
+			contenthash: None,
+			pubkey_x: None,
+			pubkey_y: None,
+		}
+	}
+
+	fn payload(addresses: &[&str]) -> QueryAddressesPayload {
+		serde_json::from_value(serde_json::json!({ "addresses": addresses })).unwrap()
+	}
+
+	#[test]
+	fn empty_queries_are_rejected_when_configured() {
+		assert_eq!(
+			ensure_not_empty(&payload(&[]), true).unwrap_err().status(),
+			StatusCode::BAD_REQUEST
+		);
+		// A query that matches nothing is still a valid query.
+		assert!(ensure_not_empty(
+			&payload(&["0x000000000000000000000000000000000000dEaD"]),
+			true
+		)
+		.is_ok());
+	}
+
+	#[test]
+	fn empty_queries_are_allowed_by_default() {
+		assert!(!Config::for_tests().reject_empty_queries);
+		assert!(ensure_not_empty(&payload(&[]), false).is_ok());
+		assert!(ensure_not_empty(
+			&payload(&["0x000000000000000000000000000000000000dEaD"]),
+			false
+		)
+		.is_ok());
+	}
+
+	#[test]
+	fn rejects_results_over_the_limit() {
+		assert_eq!(
+			ensure_within_limit(1001, 1000).unwrap_err().status(),
+			StatusCode::PAYLOAD_TOO_LARGE
+		);
+	}
+
+	#[test]
+	fn allows_results_up_to_the_limit() {
+		assert!(ensure_within_limit(0, 1000).is_ok());
+		assert!(ensure_within_limit(1000, 1000).is_ok());
+	}
+
+	#[test]
+	fn includes_timestamps_when_requested() {
+		let record = serde_json::to_value(record(name(), true)).unwrap();
+
+		assert_eq!(record["updated_at"], "2023-11-14T22:13:20");
+	}
+
+	#[test]
+	fn omits_timestamps_by_default() {
+		let payload: QueryAddressesPayload = serde_json::from_value(serde_json::json!({
+			"addresses": ["0x000000000000000000000000000000000000dEaD"],
+		}))
+		.unwrap();
+		assert!(!payload.include_timestamps);
+
+		let record = serde_json::to_value(record(name(), payload.include_timestamps)).unwrap();
+
+		assert!(record.get("updated_at").is_none());
+	}
 }
@@ -1,22 +1,125 @@
 use std::str::FromStr;
 
-use alloy::primitives::Address;
+use alloy::primitives::{keccak256, Address};
 use axum::{
-	extract::Path,
+	extract::{Path, RawQuery},
 	response::{IntoResponse, Redirect, Response},
 	Extension,
 };
 use axum_jsonschema::Json;
+use chrono::NaiveDateTime;
+use futures::FutureExt;
+use http::{header, HeaderMap, StatusCode};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
 
 use crate::{
-	config::Db,
+	config::{Config, ConfigExt, Db},
+	single_flight::{SingleFlight, SingleFlightExt},
 	types::{ErrorResponse, MovedRecord, Name, UsernameRecord},
 };
 
+/// The outcome of looking up a username or address, shared between concurrent identical requests.
+pub enum Lookup {
+	Found {
+		record: UsernameRecord,
+		etag: String,
+	},
+	Moved(String),
+	Deleted,
+	NotFound,
+}
+
+/// Characters left as-is in a path segment (RFC 3986 unreserved), everything else is percent-encoded.
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+	.remove(b'-')
+	.remove(b'.')
+	.remove(b'_')
+	.remove(b'~');
+
+pub type LookupFlight = SingleFlight<Result<Lookup, sqlx::Error>>;
+
 pub async fn query_single(
+	Extension(config): ConfigExt,
 	Extension(db): Extension<Db>,
+	Extension(single_flight): SingleFlightExt<Result<Lookup, sqlx::Error>>,
 	Path(name_or_address): Path<String>,
+	RawQuery(query): RawQuery,
+	headers: HeaderMap,
 ) -> Result<Response, ErrorResponse> {
+	let name_or_address = lookup_key(&config, &name_or_address);
+
+	let outcome = single_flight
+		.run(&name_or_address, || lookup(&db, &name_or_address))
+		.await;
+
+	match &*outcome {
+		Ok(lookup) => respond(
+			lookup,
+			config.query_single_cache_max_age,
+			&headers,
+			query.as_deref(),
+		),
+		Err(e) => {
+			tracing::error!("query_single lookup for {name_or_address:?} failed: {e}");
+			Err(ErrorResponse::server_error(
+				"Internal Server Error".to_string(),
+			))
+		},
+	}
+}
+
+/// What to look up for a request. Fully-qualified ENS names (e.g. `alice.<ens_domain>`) resolve to the bare username.
+fn lookup_key(config: &Config, name_or_address: &str) -> String {
+	config
+		.strip_ens_domain(name_or_address)
+		.unwrap_or(name_or_address)
+		.to_string()
+}
+
+/// Answer a lookup: the record (or a 304 when the client's copy is current), a redirect to its new name,
+/// or why there's no record.
+fn respond(
+	lookup: &Lookup,
+	max_age: u64,
+	headers: &HeaderMap,
+	query: Option<&str>,
+) -> Result<Response, ErrorResponse> {
+	match lookup {
+		Lookup::Found { record, etag } => {
+			let cache_headers = [
+				(header::CACHE_CONTROL, format!("public, max-age={max_age}")),
+				(header::ETAG, etag.clone()),
+			];
+
+			if matches_etag(headers, etag) {
+				return Ok((StatusCode::NOT_MODIFIED, cache_headers).into_response());
+			}
+
+			Ok((cache_headers, Json(record)).into_response())
+		},
+		Lookup::Moved(new_username) => {
+			Ok(Redirect::permanent(&moved_location(new_username, query)).into_response())
+		},
+		Lookup::Deleted => Err(ErrorResponse::gone("Record was deleted.".to_string())),
+		Lookup::NotFound => Err(ErrorResponse::not_found("Record not found.".to_string())),
+	}
+}
+
+/// Where a moved name redirects to. Usernames may contain any Unicode word character, so the name is percent-encoded,
+/// and the original query string is kept so any options survive the redirect.
+fn moved_location(new_username: &str, query: Option<&str>) -> String {
+	let query = query
+		.filter(|q| !q.is_empty())
+		.map(|q| format!("?{q}"))
+		.unwrap_or_default();
+
+	format!(
+		"/api/v1/{}{query}",
+		utf8_percent_encode(new_username, PATH_SEGMENT)
+	)
+}
+
+pub(crate) async fn lookup(db: &Db, name_or_address: &str) -> Result<Lookup, sqlx::Error> {
 	if let Some(name) = sqlx::query_as!(
 		Name,
 		r#"
@@ -27,7 +130,10 @@ pub async fn query_single(
             nullifier_hash as "nullifier_hash!",
             verification_level as "verification_level!",
             created_at as "created_at!",
-            updated_at as "updated_at!"
+            updated_at as "updated_at!",
+            contenthash,
+            pubkey_x,
+            pubkey_y
         FROM names 
         WHERE username = $1 
         UNION ALL 
@@ -38,16 +144,27 @@ pub async fn query_single(
             nullifier_hash as "nullifier_hash!",
             verification_level as "verification_level!",
             created_at as "created_at!",
-            updated_at as "updated_at!"
+            updated_at as "updated_at!",
+            contenthash,
+            pubkey_x,
+            pubkey_y
         FROM names 
         WHERE address = $1 AND username <> $1
+        ORDER BY "created_at!" ASC, "username!" ASC
+        LIMIT 1
         "#,
-		validate_address(&name_or_address)
+		validate_address(name_or_address)
 	)
 	.fetch_optional(&db.read_only)
+	.inspect(db.latency.read_only.timer())
 	.await?
 	{
-		return Ok(Json(UsernameRecord::from(name)).into_response());
+		let etag = record_etag(&name.username, name.updated_at);
+
+		return Ok(Lookup::Found {
+			record: UsernameRecord::from(name),
+			etag,
+		});
 	};
 
 	if let Some(moved) = sqlx::query_as!(
@@ -56,18 +173,40 @@ pub async fn query_single(
 		name_or_address
 	)
 	.fetch_optional(&db.read_only)
+	.inspect(db.latency.read_only.timer())
 	.await?
 	{
-		return Ok(Redirect::permanent(&format!("/api/v1/{}", moved.new_username)).into_response());
+		return Ok(Lookup::Moved(moved.new_username));
+	}
+
+	if is_deleted(db, name_or_address).await? {
+		return Ok(Lookup::Deleted);
 	}
 
-	Err(ErrorResponse::not_found("Record not found.".to_string()))
+	Ok(Lookup::NotFound)
+}
+
+/// Whether the username was deleted at its owner's request, leaving a tombstone behind.
+pub async fn is_deleted(db: &Db, username: &str) -> Result<bool, sqlx::Error> {
+	sqlx::query_scalar!(
+		r#"SELECT EXISTS(SELECT 1 FROM deleted_names WHERE username = $1) as "exists!""#,
+		username
+	)
+	.fetch_one(&db.read_only)
+	.inspect(db.latency.read_only.timer())
+	.await
 }
 
 pub fn docs(op: aide::transform::TransformOperation) -> aide::transform::TransformOperation {
-	op.description("Resolve a single username or address.")
+	op.description("Resolve a single username, ENS name or address. An address with several usernames resolves to the one registered first. Responses carry an `ETag` and short-lived `Cache-Control`; send the ETag back in `If-None-Match` to get a 304 when unchanged.")
 		.response::<404, ErrorResponse>()
+		.response_with::<410, ErrorResponse, _>(|op| {
+			op.description("The username was deleted at its owner's request.")
+		})
 		.response::<200, Json<UsernameRecord>>()
+		.response_with::<304, (), _>(|op| {
+			op.description("The record is unchanged since the ETag sent in `If-None-Match`.")
+		})
 		.response_with::<301, Redirect, _>(|op| {
 			op.description(
 				"A redirect to the new username, if the queries username has recently changed.",
@@ -75,9 +214,238 @@ pub fn docs(op: aide::transform::TransformOperation) -> aide::transform::Transfo
 		})
 }
 
+/// A strong ETag for a record, which changes whenever it's renamed or updated.
+fn record_etag(username: &str, updated_at: NaiveDateTime) -> String {
+	format!(
+		"\"{}\"",
+		hex::encode(
+			&keccak256(format!(
+				"{username}:{}",
+				updated_at.and_utc().timestamp_micros()
+			))[..16]
+		)
+	)
+}
+
+/// Whether the request's `If-None-Match` header lists the record's current ETag.
+fn matches_etag(headers: &HeaderMap, etag: &str) -> bool {
+	headers
+		.get_all(header::IF_NONE_MATCH)
+		.iter()
+		.filter_map(|value| value.to_str().ok())
+		.flat_map(|value| value.split(','))
+		.map(|tag| tag.trim().trim_start_matches("W/"))
+		.any(|tag| tag == "*" || tag == etag)
+}
+
 pub fn validate_address(name_or_address: &str) -> String {
 	Address::from_str(name_or_address).map_or_else(
 		|_| name_or_address.to_string(),
 		|address| address.to_checksum(None),
 	)
 }
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+
+	use chrono::{NaiveDate, TimeDelta};
+	use http::HeaderValue;
+	use sqlx::PgPool;
+
+	use super::*;
+	use crate::latency::Latency;
+
+	const ADDRESS: &str = "0x000000000000000000000000000000000000dEaD";
+
+	fn updated_at() -> NaiveDateTime {
+		NaiveDate::from_ymd_opt(2025, 1, 1)
+			.unwrap()
+			.and_hms_opt(12, 0, 0)
+			.unwrap()
+	}
+
+	fn if_none_match(value: &str) -> HeaderMap {
+		let mut headers = HeaderMap::new();
+		headers.insert(header::IF_NONE_MATCH, HeaderValue::from_str(value).unwrap());
+		headers
+	}
+
+	#[test]
+	fn etag_is_stable_for_an_unchanged_record() {
+		let etag = record_etag("alice", updated_at());
+
+		assert_eq!(etag, record_etag("alice", updated_at()));
+		assert!(etag.starts_with('"') && etag.ends_with('"'));
+	}
+
+	#[test]
+	fn etag_changes_when_the_record_is_updated() {
+		let etag = record_etag("alice", updated_at());
+
+		assert_ne!(
+			etag,
+			record_etag("alice", updated_at() + TimeDelta::milliseconds(1))
+		);
+		assert_ne!(etag, record_etag("bob", updated_at()));
+	}
+
+	#[test]
+	fn matches_the_current_etag() {
+		let etag = record_etag("alice", updated_at());
+		let headers = if_none_match(&etag);
+
+		assert!(matches_etag(&headers, &etag));
+		assert!(matches_etag(&if_none_match("*"), &etag));
+		assert!(matches_etag(
+			&if_none_match("\"abc\", W/\"def\""),
+			"\"def\""
+		));
+	}
+
+	#[test]
+	fn does_not_match_a_stale_etag() {
+		let etag = record_etag("alice", updated_at());
+		let stale = record_etag("alice", updated_at() - TimeDelta::seconds(1));
+		let headers = if_none_match(&stale);
+
+		assert!(!matches_etag(&headers, &etag));
+		assert!(!matches_etag(&HeaderMap::new(), &etag));
+	}
+
+	fn status(lookup: &Lookup, headers: &HeaderMap) -> StatusCode {
+		respond(lookup, 60, headers, None).map_or_else(|e| e.status(), |r| r.status())
+	}
+
+	async fn register(db: &Db, username: &str, created_at: NaiveDateTime) {
+		Name {
+			address: ADDRESS.to_string(),
+			username: username.to_string(),
+			profile_picture_url: None,
+			nullifier_hash: format!("0x{username}"),
+			verification_level: "orb".to_string(),
+			created_at,
+			updated_at: created_at,
+			contenthash: None,
+			pubkey_x: None,
+			pubkey_y: None,
+		}
+		.insert_with(&db.read_write, &["world.id".to_string()])
+		.await
+		.unwrap();
+	}
+
+	async fn username_for_address(db: &Db) -> String {
+		let Lookup::Found { record, .. } = lookup(db, ADDRESS).await.unwrap() else {
+			panic!("{ADDRESS} should resolve to a username");
+		};
+
+		record.username
+	}
+
+	#[sqlx::test]
+	#[ignore = "needs a Postgres database at DATABASE_URL"]
+	async fn address_with_several_usernames_resolves_to_the_first_registered(pool: PgPool) {
+		let db = Db {
+			read_only: pool.clone(),
+			read_write: pool,
+			latency: Arc::new(Latency::default()),
+		};
+		// Registered later, but first alphabetically, so only the creation time can pick the other one.
+		register(&db, "alice", updated_at() + TimeDelta::days(1)).await;
+		register(&db, "bob", updated_at()).await;
+
+		for _ in 0..5 {
+			assert_eq!(username_for_address(&db).await, "bob");
+		}
+	}
+
+	#[sqlx::test]
+	#[ignore = "needs a Postgres database at DATABASE_URL"]
+	async fn usernames_registered_at_once_resolve_alphabetically(pool: PgPool) {
+		let db = Db {
+			read_only: pool.clone(),
+			read_write: pool,
+			latency: Arc::new(Latency::default()),
+		};
+		register(&db, "bob", updated_at()).await;
+		register(&db, "alice", updated_at()).await;
+
+		assert_eq!(username_for_address(&db).await, "alice");
+	}
+
+	#[test]
+	fn ens_names_look_up_the_bare_username() {
+		let config = Config::for_tests();
+
+		assert_eq!(lookup_key(&config, "alice"), "alice");
+		assert_eq!(lookup_key(&config, "alice.world.id"), "alice");
+		// Other domains aren't ours, so they're looked up as sent (and won't match a username).
+		assert_eq!(lookup_key(&config, "alice.eth"), "alice.eth");
+		assert_eq!(lookup_key(&config, ADDRESS), ADDRESS);
+	}
+
+	#[test]
+	fn tombstoned_names_are_gone() {
+		assert_eq!(
+			status(&Lookup::Deleted, &HeaderMap::new()),
+			StatusCode::GONE
+		);
+	}
+
+	#[test]
+	fn unknown_names_are_not_found() {
+		assert_eq!(
+			status(&Lookup::NotFound, &HeaderMap::new()),
+			StatusCode::NOT_FOUND
+		);
+	}
+
+	#[test]
+	fn found_names_are_cacheable() {
+		let etag = record_etag("alice", updated_at());
+		let lookup = Lookup::Found {
+			record: UsernameRecord {
+				username: "alice".to_string(),
+				address: crate::types::Address(Address::ZERO),
+				profile_picture_url: None,
+				updated_at: None,
+			},
+			etag: etag.clone(),
+		};
+
+		let response = respond(&lookup, 60, &HeaderMap::new(), None).unwrap();
+		assert_eq!(response.status(), StatusCode::OK);
+		assert_eq!(
+			response.headers().get(header::CACHE_CONTROL).unwrap(),
+			"public, max-age=60"
+		);
+		assert_eq!(response.headers().get(header::ETAG).unwrap(), etag.as_str());
+
+		assert_eq!(
+			status(&lookup, &if_none_match(&etag)),
+			StatusCode::NOT_MODIFIED
+		);
+	}
+
+	#[test]
+	fn percent_encodes_moved_usernames() {
+		assert_eq!(moved_location("alice", None), "/api/v1/alice");
+		assert_eq!(moved_location("françois", None), "/api/v1/fran%C3%A7ois");
+		assert_eq!(moved_location("a/b?c#d", None), "/api/v1/a%2Fb%3Fc%23d");
+	}
+
+	#[test]
+	fn keeps_the_query_string_on_moved_names() {
+		assert_eq!(
+			moved_location("alice", Some("minimized=true")),
+			"/api/v1/alice?minimized=true"
+		);
+		assert_eq!(
+			moved_location("françois", Some("minimized=true&fallback=none")),
+			"/api/v1/fran%C3%A7ois?minimized=true&fallback=none"
+		);
+		// A bare `?` carries nothing worth redirecting with.
+		assert_eq!(moved_location("alice", Some("")), "/api/v1/alice");
+	}
+}
@@ -1,75 +1,80 @@
 use axum::Extension;
 use axum_jsonschema::Json;
+use futures::FutureExt;
 use http::StatusCode;
-use idkit::session::VerificationLevel;
 
 use crate::{
-	blocklist::BlocklistExt,
-	config::{ConfigExt, Db, DEVICE_USERNAME_REGEX, USERNAME_REGEX},
+	audit,
+	blocklist::{Blocklist, BlocklistExt},
+	config::{Config, ConfigExt, Db},
 	types::{ErrorResponse, Name, RegisterUsernamePayload},
+	validation::{ensure_address_below_limit, ensure_valid_username, verification_error},
 	verify,
 };
 
-#[allow(dependency_on_unit_never_type_fallback)]
 pub async fn register_username(
 	Extension(config): ConfigExt,
 	Extension(db): Extension<Db>,
 	Extension(blocklist): BlocklistExt,
 	Json(payload): Json<RegisterUsernamePayload>,
 ) -> Result<StatusCode, ErrorResponse> {
-	match verify::dev_portal_verify_proof(
+	let mutation = audit::Mutation::new(
+		"register",
+		payload.username.clone(),
+		Some(payload.address.to_checksum(None)),
+		payload.nullifier_hash.clone(),
+	);
+
+	let outcome = register(&config, &db, &blocklist, payload).await;
+	mutation.record(&outcome);
+
+	outcome
+}
+
+#[allow(dependency_on_unit_never_type_fallback)]
+async fn register(
+	config: &Config,
+	db: &Db,
+	blocklist: &Blocklist,
+	payload: RegisterUsernamePayload,
+) -> Result<StatusCode, ErrorResponse> {
+	verify::dev_portal_verify_proof(
 		payload.into_proof(),
 		config.wld_app_id.to_string(),
 		"username",
-		(&payload.username, payload.address.to_checksum(None)),
+		(
+			&payload.username,
+			payload.address.to_checksum(None),
+			// Packs to no bytes when unset, so older clients' signals still verify.
+			payload
+				.contenthash
+				.as_ref()
+				.map(ToString::to_string)
+				.unwrap_or_default(),
+		),
 		config.developer_portal_url.clone(),
+		&config.developer_portal_verify_path,
+		config.verify_options(),
 	)
 	.await
-	{
-		Ok(()) => {},
-		Err(verify::Error::Verification(e)) => {
-			tracing::error!(
-				"Register Verification Error: {}, Payload: {:?}",
-				e.detail,
-				payload
-			);
-			return Err(ErrorResponse::validation_error(e.detail));
-		},
-		Err(e) => {
-			tracing::error!(
-				"Register Server Error: {}, Payload: {:?}",
-				e.to_string(),
-				payload
-			);
-			return Err(ErrorResponse::server_error(
-				"Failed to verify World ID proof".to_string(),
-			));
-		},
-	};
-
-	let username_regex = match payload.verification_level.0 {
-		VerificationLevel::Orb => USERNAME_REGEX.clone(),
-		VerificationLevel::Device => DEVICE_USERNAME_REGEX.clone(),
-	};
-
-	if !username_regex.is_match(&payload.username) {
-		return Err(ErrorResponse::validation_error(
-			"Username does not match the required pattern".to_string(),
-		));
-	}
+	.map_err(|e| verification_error("Register", e))?;
 
-	blocklist
-		.ensure_valid(&payload.username)
-		.map_err(|e| ErrorResponse::validation_error(e.to_string()))?;
+	ensure_valid_username(
+		&payload.username,
+		&payload.verification_level.0,
+		&payload.nullifier_hash,
+		blocklist,
+	)?;
 
 	let uniqueness_check = sqlx::query!(
 		"SELECT
 			EXISTS(SELECT 1 FROM names WHERE nullifier_hash = $2) AS world_id,
-			EXISTS(SELECT 1 FROM names WHERE LOWER(username) = LOWER($1) UNION SELECT 1 FROM old_names where LOWER(old_username) = LOWER($1)) AS username",
+			EXISTS(SELECT 1 FROM names WHERE LOWER(username) = LOWER($1) UNION SELECT 1 FROM old_names where LOWER(old_username) = LOWER($1) UNION SELECT 1 FROM deleted_names WHERE LOWER(username) = LOWER($1)) AS username",
 			&payload.username,
 			&payload.nullifier_hash
 		)
 		.fetch_one(&db.read_write)
+		.inspect(db.latency.read_write.timer())
 		.await?;
 
 	if uniqueness_check.username.unwrap_or_default() {
@@ -84,16 +89,29 @@ pub async fn register_username(
 		));
 	}
 
+	// The limit is checked in the transaction that inserts the name, so concurrent registrations can't both slip under it.
+	let mut tx = db.read_write.begin().await?;
+
+	ensure_address_below_limit(
+		&mut tx,
+		&payload.address.to_checksum(None),
+		config.max_usernames_per_address,
+	)
+	.await?;
+
 	Name::new(
 		payload.username,
 		&payload.address,
 		payload.profile_picture_url,
 		payload.nullifier_hash,
 		&payload.verification_level,
+		payload.contenthash,
 	)
-	.insert(&db.read_write, "names")
+	.insert_with(&mut *tx, &config.ens_domains)
 	.await?;
 
+	tx.commit().await?;
+
 	Ok(StatusCode::CREATED)
 }
 
@@ -1,84 +1,77 @@
 use axum::Extension;
 use axum_jsonschema::Json;
+use futures::FutureExt;
 use http::StatusCode;
-use idkit::session::VerificationLevel;
 
 use crate::{
-	blocklist::BlocklistExt,
-	config::{ConfigExt, Db, DEVICE_USERNAME_REGEX, USERNAME_REGEX},
+	audit,
+	blocklist::{Blocklist, BlocklistExt},
+	config::{Config, ConfigExt, Db},
 	types::{ErrorResponse, Name, RenamePayload},
+	utils::{name_nodes, secure_eq},
+	validation::{ensure_valid_username, verification_error},
 	verify,
 };
 
-#[allow(clippy::too_many_lines)] // TODO: refactor
-#[allow(dependency_on_unit_never_type_fallback)]
 pub async fn rename(
 	Extension(config): ConfigExt,
 	Extension(db): Extension<Db>,
 	Extension(blocklist): BlocklistExt,
 	Json(payload): Json<RenamePayload>,
 ) -> Result<StatusCode, ErrorResponse> {
+	let mutation = audit::Mutation::new(
+		"rename",
+		payload.old_username.clone(),
+		None,
+		payload.nullifier_hash.clone(),
+	);
+
+	let outcome = rename_username(&config, &db, &blocklist, &payload).await;
+	mutation.record(&outcome);
+
+	outcome
+}
+
+#[allow(clippy::too_many_lines)] // TODO: refactor
+#[allow(dependency_on_unit_never_type_fallback)]
+async fn rename_username(
+	config: &Config,
+	db: &Db,
+	blocklist: &Blocklist,
+	payload: &RenamePayload,
+) -> Result<StatusCode, ErrorResponse> {
+	// Checked before the proof, so an invalid rename doesn't cost a verification call.
+	ensure_valid_rename(payload, blocklist)?;
+
 	let Some(record) = sqlx::query_as!(
 		Name,
 		"SELECT * FROM names WHERE username = $1",
 		&payload.old_username
 	)
 	.fetch_optional(&db.read_write)
+	.inspect(db.latency.read_write.timer())
 	.await?
 	else {
 		return Err(ErrorResponse::not_found("Username not found".to_string()));
 	};
 
-	if record.nullifier_hash != payload.nullifier_hash {
+	if !secure_eq(&record.nullifier_hash, &payload.nullifier_hash) {
 		return Err(ErrorResponse::unauthorized(
 			"You can't update this name".to_string(),
 		));
 	}
 
-	match verify::dev_portal_verify_proof(
+	verify::dev_portal_verify_proof(
 		payload.into_proof(),
 		config.wld_app_id.to_string(),
 		"username",
 		(&payload.old_username, &payload.new_username),
 		config.developer_portal_url.clone(),
+		&config.developer_portal_verify_path,
+		config.verify_options(),
 	)
 	.await
-	{
-		Ok(()) => {},
-		Err(verify::Error::Verification(e)) => {
-			tracing::error!(
-				"Rename Verification Error: {}, Payload: {:?}",
-				e.detail,
-				payload
-			);
-			return Err(ErrorResponse::validation_error(e.detail));
-		},
-		Err(e) => {
-			tracing::error!(
-				"Rename Server Error: {}, Payload: {:?}",
-				e.to_string(),
-				payload
-			);
-			return Err(ErrorResponse::server_error(
-				"Failed to verify World ID proof".to_string(),
-			));
-		},
-	};
-
-	let username_regex = match payload.verification_level.0 {
-		VerificationLevel::Orb => USERNAME_REGEX.clone(),
-		VerificationLevel::Device => DEVICE_USERNAME_REGEX.clone(),
-	};
-
-	if !username_regex.is_match(&payload.new_username) {
-		return Err(ErrorResponse::validation_error(
-			"Username does not match the required pattern".to_string(),
-		));
-	}
-
-	blocklist
-		.ensure_valid(&payload.new_username)
-		.map_err(|e| ErrorResponse::validation_error(e.to_string()))?;
+	.map_err(|e| verification_error("Rename", e))?;
 
 	let uniqueness_check = sqlx::query!(
 		"SELECT
@@ -86,12 +79,15 @@ pub async fn rename(
             EXISTS(SELECT 1 FROM names WHERE LOWER(username) = LOWER($2) 
                 UNION 
                 SELECT 1 FROM old_names where LOWER(old_username) = LOWER($2) AND LOWER(new_username) != LOWER($1)
+                UNION
+                SELECT 1 FROM deleted_names WHERE LOWER(username) = LOWER($2)
             ) AS username
         ",
 		&payload.old_username,
 		&payload.new_username,
 	)
 	.fetch_one(&db.read_write)
+	.inspect(db.latency.read_write.timer())
 	.await?;
 
 	if uniqueness_check.username.unwrap_or_default() {
@@ -111,14 +107,30 @@ pub async fn rename(
 		.await?;
 	}
 
+	// The old name's nodes reference it, so they go before the rename and are recomputed after.
+	sqlx::query!(
+		"DELETE FROM name_nodes WHERE username = $1",
+		&payload.old_username
+	)
+	.execute(&mut *tx)
+	.await?;
+
 	sqlx::query!(
-		"UPDATE names SET username = $1 WHERE username = $2",
+		"UPDATE names SET username = $1, updated_at = NOW() WHERE username = $2",
 		&payload.new_username,
 		&payload.old_username,
 	)
 	.execute(&mut *tx)
 	.await?;
 
+	sqlx::query!(
+		"INSERT INTO name_nodes (node, username) SELECT UNNEST($1::bytea[]), $2::varchar",
+		&name_nodes(&payload.new_username, &config.ens_domains),
+		&payload.new_username,
+	)
+	.execute(&mut *tx)
+	.await?;
+
 	sqlx::query!(
 		"INSERT INTO old_names (old_username, new_username) VALUES ($1, $2)",
 		&payload.old_username,
@@ -132,6 +144,90 @@ pub async fn rename(
 	Ok(StatusCode::OK)
 }
 
+/// Ensure a rename can go ahead. Only the new username is validated, so legacy names that predate the current
+/// format can still be renamed away.
+fn ensure_valid_rename(
+	payload: &RenamePayload,
+	blocklist: &Blocklist,
+) -> Result<(), ErrorResponse> {
+	if payload
+		.old_username
+		.eq_ignore_ascii_case(&payload.new_username)
+	{
+		return Err(ErrorResponse::validation_error(
+			"New username must be different from the current one".to_string(),
+		));
+	}
+
+	ensure_valid_username(
+		&payload.new_username,
+		&payload.verification_level.0,
+		&payload.nullifier_hash,
+		blocklist,
+	)
+}
+
 pub fn docs(op: aide::transform::TransformOperation) -> aide::transform::TransformOperation {
 	op.description("Change your World App username to a new one.")
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn rename_payload(old_username: &str, new_username: &str) -> RenamePayload {
+		serde_json::from_value(serde_json::json!({
+			"proof": "0x00",
+			"merkle_root": "0x00",
+			"old_username": old_username,
+			"new_username": new_username,
+			"nullifier_hash": "0x1",
+			"verification_level": "orb",
+		}))
+		.unwrap()
+	}
+
+	fn blocklist() -> Blocklist {
+		Blocklist::new("admin", "badword", "")
+	}
+
+	#[test]
+	fn renames_away_from_legacy_format_usernames() {
+		// Imported names that no longer match the username rules: too short, too long, or with other characters.
+		for legacy in ["ab", "a_very_long_legacy_name", "Legacy-Name", "_alice"] {
+			assert!(
+				ensure_valid_rename(&rename_payload(legacy, "alice"), &blocklist()).is_ok(),
+				"{legacy} should be renameable"
+			);
+		}
+	}
+
+	#[test]
+	fn validates_the_new_username() {
+		assert!(
+			ensure_valid_rename(&rename_payload("alice", "Legacy-Name"), &blocklist()).is_err()
+		);
+		assert!(ensure_valid_rename(&rename_payload("alice", "admin"), &blocklist()).is_err());
+	}
+
+	#[test]
+	fn rejects_renaming_to_the_same_username() {
+		for new_username in ["alice", "ALICE", "Alice"] {
+			let error = ensure_valid_rename(&rename_payload("alice", new_username), &blocklist())
+				.unwrap_err();
+
+			assert_eq!(
+				error.status(),
+				StatusCode::UNPROCESSABLE_ENTITY,
+				"{new_username}"
+			);
+		}
+	}
+
+	#[test]
+	fn accepts_a_valid_rename() {
+		assert!(ensure_valid_rename(&rename_payload("alice", "alicia"), &blocklist()).is_ok());
+		// A name that merely contains the old one is a different username.
+		assert!(ensure_valid_rename(&rename_payload("alice", "alice2"), &blocklist()).is_ok());
+	}
+}
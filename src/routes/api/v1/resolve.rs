@@ -0,0 +1,147 @@
+use std::future::Future;
+
+use axum::{extract::Path, Extension};
+use axum_jsonschema::Json;
+use futures::FutureExt;
+
+use super::query_single::is_deleted;
+use crate::{
+	config::Db,
+	types::{ErrorResponse, MovedRecord, Name, ResolvedUsernameRecord, UsernameRecord},
+};
+
+/// How many renames to follow before giving up, so a corrupted chain can't loop forever.
+const MAX_RENAME_DEPTH: usize = 8;
+
+/// Where a single username leads.
+enum Hop<T> {
+	Found(T),
+	Moved(String),
+	Deleted,
+	NotFound,
+}
+
+pub async fn resolve(
+	Extension(db): Extension<Db>,
+	Path(name): Path<String>,
+) -> Result<Json<ResolvedUsernameRecord>, ErrorResponse> {
+	let record = follow_renames(&name, |username| hop(&db, username)).await?;
+	let redirected_from = (record.username != name).then_some(name);
+
+	Ok(Json(ResolvedUsernameRecord {
+		record: UsernameRecord::from(record),
+		redirected_from,
+	}))
+}
+
+/// Follow renames from `name` until reaching a record, a tombstone or a dead end.
+async fn follow_renames<T, F, Fut>(name: &str, mut hop: F) -> Result<T, ErrorResponse>
+where
+	F: FnMut(String) -> Fut,
+	Fut: Future<Output = Result<Hop<T>, sqlx::Error>>,
+{
+	let mut username = name.to_string();
+
+	for _ in 0..=MAX_RENAME_DEPTH {
+		match hop(username).await? {
+			Hop::Found(record) => return Ok(record),
+			Hop::Moved(new_username) => username = new_username,
+			Hop::Deleted => return Err(ErrorResponse::gone("Record was deleted.".to_string())),
+			Hop::NotFound => return Err(ErrorResponse::not_found("Record not found.".to_string())),
+		}
+	}
+
+	Err(ErrorResponse::server_error(
+		"Rename chain is too long to resolve".to_string(),
+	))
+}
+
+async fn hop(db: &Db, username: String) -> Result<Hop<Name>, sqlx::Error> {
+	if let Some(record) = sqlx::query_as!(Name, "SELECT * FROM names WHERE username = $1", username)
+		.fetch_optional(&db.read_only)
+		.inspect(db.latency.read_only.timer())
+		.await?
+	{
+		return Ok(Hop::Found(record));
+	}
+
+	if let Some(moved) = sqlx::query_as!(
+		MovedRecord,
+		"SELECT * FROM old_names WHERE old_username = $1",
+		username
+	)
+	.fetch_optional(&db.read_only)
+	.inspect(db.latency.read_only.timer())
+	.await?
+	{
+		return Ok(Hop::Moved(moved.new_username));
+	}
+
+	// Deleted names answer 410 here too, like `GET /api/v1/:name`.
+	if is_deleted(db, &username).await? {
+		return Ok(Hop::Deleted);
+	}
+
+	Ok(Hop::NotFound)
+}
+
+pub fn docs(op: aide::transform::TransformOperation) -> aide::transform::TransformOperation {
+	op.description("Resolve a username to its current record, following renames server-side instead of redirecting.")
+		.response::<404, ErrorResponse>()
+		.response_with::<410, ErrorResponse, _>(|op| {
+			op.description("The username, or the one it was renamed to, was deleted at its owner's request.")
+		})
+}
+
+#[cfg(test)]
+mod tests {
+	use http::StatusCode;
+
+	use super::*;
+
+	/// `alice_older` -> `alice_old` -> `alice`, `bobby_old` -> `bobby` (deleted), and a loop between `loop_a` and `loop_b`.
+	#[allow(clippy::unused_async)]
+	async fn chain(username: String) -> Result<Hop<String>, sqlx::Error> {
+		Ok(match username.as_str() {
+			"alice" => Hop::Found(username),
+			"alice_old" => Hop::Moved("alice".to_string()),
+			"alice_older" => Hop::Moved("alice_old".to_string()),
+			"bobby" => Hop::Deleted,
+			"bobby_old" => Hop::Moved("bobby".to_string()),
+			"loop_a" => Hop::Moved("loop_b".to_string()),
+			"loop_b" => Hop::Moved("loop_a".to_string()),
+			_ => Hop::NotFound,
+		})
+	}
+
+	async fn status(name: &str) -> StatusCode {
+		follow_renames(name, chain).await.unwrap_err().status()
+	}
+
+	#[tokio::test]
+	async fn resolves_a_current_username() {
+		assert_eq!(follow_renames("alice", chain).await.unwrap(), "alice");
+	}
+
+	#[tokio::test]
+	async fn follows_renames() {
+		assert_eq!(follow_renames("alice_old", chain).await.unwrap(), "alice");
+		assert_eq!(follow_renames("alice_older", chain).await.unwrap(), "alice");
+	}
+
+	#[tokio::test]
+	async fn reports_deleted_usernames_as_gone() {
+		assert_eq!(status("bobby").await, StatusCode::GONE);
+		assert_eq!(status("bobby_old").await, StatusCode::GONE);
+	}
+
+	#[tokio::test]
+	async fn reports_unknown_usernames_as_not_found() {
+		assert_eq!(status("carol").await, StatusCode::NOT_FOUND);
+	}
+
+	#[tokio::test]
+	async fn stops_following_a_looping_chain() {
+		assert_eq!(status("loop_a").await, StatusCode::INTERNAL_SERVER_ERROR);
+	}
+}
@@ -1,48 +1,342 @@
 use crate::{
-	config::{Db, USERNAME_SEARCH_REGEX},
-	types::{ErrorResponse, NameSearch, UsernameRecord},
+	config::{ConfigExt, Db, USERNAME_SEARCH_REGEX},
+	types::{ErrorResponse, NameSearch, SearchCandidate, SearchParams, SearchSort, UsernameRecord},
 };
+use alloy::primitives::keccak256;
 use axum::{
-	extract::Path,
+	extract::{Path, Query},
 	response::{IntoResponse, Response},
 	Extension,
 };
 use axum_jsonschema::Json;
+use futures::FutureExt;
+use http::{header, HeaderName, HeaderValue};
+use std::cmp::Ordering;
+
+/// How many of the closest trigram matches are re-ranked into the results.
+const SEARCH_CANDIDATES: i64 = 50;
+
+/// How many results a search returns.
+const SEARCH_RESULTS: usize = 10;
+
+/// Explains why a search returned no results without running, e.g. `query_too_short`.
+const SEARCH_REASON_HEADER: &str = "x-search-reason";
 
 pub async fn search(
+	Extension(config): ConfigExt,
 	Extension(db): Extension<Db>,
 	Path(username): Path<String>,
+	Query(params): Query<SearchParams>,
 ) -> Result<Response, ErrorResponse> {
 	let lowercase_username = username.to_lowercase();
 
 	if !USERNAME_SEARCH_REGEX.is_match(&lowercase_username) {
-		return Ok(Json(Vec::<UsernameRecord>::new()).into_response());
+		return Ok(skipped(None));
+	}
+
+	if is_too_short(&lowercase_username, config.search_min_query_length) {
+		return Ok(skipped(Some("query_too_short")));
 	}
 
-	let names = sqlx::query_as!(
-		NameSearch,
-		"SELECT username,
+	// The candidates are picked by trigram distance alone, so Postgres can walk the GIST index in KNN order.
+	// Only that small set is re-ranked, with exact and prefix matches first and the requested sort applied.
+	let candidates = sqlx::query_as!(
+		SearchCandidate,
+		r#"SELECT username,
 			address,
-			profile_picture_url
+			profile_picture_url,
+			verification_level,
+			updated_at,
+			username <-> $1 AS "distance!"
 		FROM names
 		WHERE username % $1
 		ORDER BY username <-> $1
-		LIMIT 10;",
-		lowercase_username
+		LIMIT $2"#,
+		lowercase_username,
+		SEARCH_CANDIDATES
 	)
 	.fetch_all(&db.read_only)
+	.inspect(db.latency.read_only.timer())
 	.await?;
 
-	Ok(Json(
-		names
-			.into_iter()
-			.map(UsernameRecord::from)
-			.collect::<Vec<UsernameRecord>>(),
+	let names = rank(candidates, &lowercase_username, params.sort);
+
+	let records = names
+		.into_iter()
+		.filter_map(|name| {
+			UsernameRecord::try_from(NameSearch::from(name))
+				.inspect_err(|e| tracing::warn!("Skipping name in search results: {e}"))
+				.ok()
+		})
+		.collect::<Vec<UsernameRecord>>();
+
+	Ok(cached(records, config.search_cache_max_age)?)
+}
+
+/// Order candidates for a lowercase query: an exact (case-insensitive) match always comes first, then the requested
+/// sort, then prefix matches, then the closest by trigram distance. Only the top `SEARCH_RESULTS` are kept.
+fn rank(
+	mut candidates: Vec<SearchCandidate>,
+	query: &str,
+	sort: SearchSort,
+) -> Vec<SearchCandidate> {
+	let exact = |c: &SearchCandidate| c.username.to_lowercase() == query;
+	let prefix = |c: &SearchCandidate| c.username.to_lowercase().starts_with(query);
+	let orb = |c: &SearchCandidate| c.verification_level == "orb";
+
+	candidates.sort_by(|a, b| {
+		exact(b)
+			.cmp(&exact(a))
+			.then_with(|| match sort {
+				SearchSort::Relevance => Ordering::Equal,
+				SearchSort::Verification => orb(b).cmp(&orb(a)),
+				SearchSort::Recent => b.updated_at.cmp(&a.updated_at),
+			})
+			.then_with(|| prefix(b).cmp(&prefix(a)))
+			.then_with(|| a.distance.total_cmp(&b.distance))
+	});
+	candidates.truncate(SEARCH_RESULTS);
+
+	candidates
+}
+
+/// Very short queries match most usernames, so they're skipped rather than run as a meaningless broad search.
+fn is_too_short(query: &str, min_length: usize) -> bool {
+	query.chars().count() < min_length
+}
+
+/// Search results, cacheable for `max_age` seconds and tagged by their contents.
+fn cached(records: Vec<UsernameRecord>, max_age: u64) -> Result<Response, serde_json::Error> {
+	let etag = format!(
+		"\"{}\"",
+		hex::encode(&keccak256(serde_json::to_vec(&records)?)[..16])
+	);
+
+	Ok((
+		[
+			(header::CACHE_CONTROL, format!("public, max-age={max_age}")),
+			(header::ETAG, etag),
+		],
+		Json(records),
 	)
-	.into_response())
+		.into_response())
+}
+
+/// An empty result for a query that wasn't run, optionally saying why. Never cached.
+fn skipped(reason: Option<&'static str>) -> Response {
+	let mut response = (
+		[(header::CACHE_CONTROL, "no-store")],
+		Json(Vec::<UsernameRecord>::new()),
+	)
+		.into_response();
+
+	if let Some(reason) = reason {
+		response.headers_mut().insert(
+			HeaderName::from_static(SEARCH_REASON_HEADER),
+			HeaderValue::from_static(reason),
+		);
+	}
+
+	response
 }
 
 pub fn docs(op: aide::transform::TransformOperation) -> aide::transform::TransformOperation {
-	op.description("Search for up to 10 usernames. Accepts 1 to 14, only valid username characters to search with.")
+	op.description("Search for up to 10 usernames. Accepts 1 to 14, only valid username characters to search with. Queries shorter than the configured minimum length return an empty list with an `x-search-reason: query_too_short` header. An exact username match always comes first, followed by the rest ordered by relevance unless `sort` is `verification` (orb-verified users first) or `recent` (most recently updated first).")
 		.response::<200, Json<Vec<UsernameRecord>>>()
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn record(username: &str) -> UsernameRecord {
+		UsernameRecord::try_from(NameSearch {
+			username: username.to_string(),
+			address: "0x000000000000000000000000000000000000dEaD".to_string(),
+			profile_picture_url: None,
+		})
+		.unwrap()
+	}
+
+	fn header<'a>(response: &'a Response, name: &str) -> Option<&'a str> {
+		response.headers().get(name).map(|v| v.to_str().unwrap())
+	}
+
+	#[test]
+	fn results_are_cacheable() {
+		let response = cached(vec![record("alice")], 300).unwrap();
+
+		assert_eq!(
+			header(&response, "cache-control"),
+			Some("public, max-age=300")
+		);
+		let etag = header(&response, "etag").unwrap();
+		assert!(etag.starts_with('"') && etag.ends_with('"'));
+	}
+
+	#[test]
+	fn etag_follows_the_results() {
+		let etag = |records| {
+			header(&cached(records, 300).unwrap(), "etag")
+				.unwrap()
+				.to_string()
+		};
+
+		assert_eq!(etag(vec![record("alice")]), etag(vec![record("alice")]));
+		assert_ne!(etag(vec![record("alice")]), etag(vec![record("alicia")]));
+		assert_ne!(etag(vec![record("alice")]), etag(vec![]));
+	}
+
+	fn candidate(username: &str, distance: f32) -> SearchCandidate {
+		SearchCandidate {
+			username: username.to_string(),
+			address: "0x000000000000000000000000000000000000dEaD".to_string(),
+			profile_picture_url: None,
+			verification_level: "device".to_string(),
+			updated_at: chrono::DateTime::from_timestamp(0, 0).unwrap().naive_utc(),
+			distance,
+		}
+	}
+
+	fn ranked(candidates: Vec<SearchCandidate>, query: &str, sort: SearchSort) -> Vec<String> {
+		rank(candidates, query, sort)
+			.into_iter()
+			.map(|c| c.username)
+			.collect()
+	}
+
+	#[test]
+	fn exact_match_outranks_closer_fuzzy_matches() {
+		// Trigram distance can favor a fuzzy match over the exact username.
+		let candidates = || {
+			vec![
+				candidate("alicee", 0.1),
+				candidate("malice", 0.2),
+				candidate("Alice", 0.3),
+			]
+		};
+
+		assert_eq!(
+			ranked(candidates(), "alice", SearchSort::Relevance),
+			["Alice", "alicee", "malice"]
+		);
+		for sort in [SearchSort::Verification, SearchSort::Recent] {
+			assert_eq!(ranked(candidates(), "alice", sort)[0], "Alice");
+		}
+	}
+
+	#[test]
+	fn prefix_matches_outrank_other_fuzzy_matches() {
+		let candidates = vec![
+			candidate("malice", 0.1),
+			candidate("alicia", 0.3),
+			candidate("palic", 0.2),
+		];
+
+		assert_eq!(
+			ranked(candidates, "alic", SearchSort::Relevance),
+			["alicia", "malice", "palic"]
+		);
+	}
+
+	#[test]
+	fn applies_the_requested_sort_after_the_exact_match() {
+		let candidates = || {
+			vec![
+				candidate("alice", 0.6),
+				candidate("alicee", 0.1),
+				SearchCandidate {
+					verification_level: "orb".to_string(),
+					..candidate("alicia", 0.4)
+				},
+				SearchCandidate {
+					updated_at: chrono::DateTime::from_timestamp(1_700_000_000, 0)
+						.unwrap()
+						.naive_utc(),
+					..candidate("alicja", 0.5)
+				},
+			]
+		};
+
+		assert_eq!(
+			ranked(candidates(), "alice", SearchSort::Verification),
+			["alice", "alicia", "alicee", "alicja"]
+		);
+		assert_eq!(
+			ranked(candidates(), "alice", SearchSort::Recent),
+			["alice", "alicja", "alicee", "alicia"]
+		);
+	}
+
+	#[test]
+	fn keeps_the_top_results() {
+		let candidates = (0..50u8)
+			.map(|i| candidate(&format!("alice{i}"), f32::from(i)))
+			.collect();
+
+		let results = ranked(candidates, "alice", SearchSort::Relevance);
+
+		assert_eq!(results.len(), SEARCH_RESULTS);
+		assert_eq!(results[0], "alice0");
+	}
+
+	#[test]
+	fn skips_queries_below_the_minimum_length() {
+		assert!(is_too_short("a", 2));
+		assert!(!is_too_short("al", 2));
+		assert!(!is_too_short("alice", 2));
+		// Length counts characters, not bytes.
+		assert!(is_too_short("é", 2));
+		// The default minimum of 1 keeps the previous behavior of running every valid query.
+		assert!(!is_too_short("a", 1));
+	}
+
+	#[tokio::test]
+	async fn too_short_queries_return_no_results_with_a_reason() {
+		let response = skipped(Some("query_too_short"));
+
+		assert_eq!(response.status(), http::StatusCode::OK);
+		assert_eq!(
+			header(&response, SEARCH_REASON_HEADER),
+			Some("query_too_short")
+		);
+		let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+			.await
+			.unwrap();
+		assert_eq!(&body[..], b"[]");
+	}
+
+	#[tokio::test]
+	async fn normal_queries_return_their_results() {
+		let response = cached(vec![record("alice"), record("alicia")], 300).unwrap();
+
+		assert_eq!(response.status(), http::StatusCode::OK);
+		assert_eq!(header(&response, SEARCH_REASON_HEADER), None);
+		let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+			.await
+			.unwrap();
+		let usernames = serde_json::from_slice::<Vec<UsernameRecord>>(&body)
+			.unwrap()
+			.into_iter()
+			.map(|record| record.username)
+			.collect::<Vec<_>>();
+		assert_eq!(usernames, ["alice", "alicia"]);
+	}
+
+	#[test]
+	fn skipped_queries_are_not_cached() {
+		let response = skipped(None);
+
+		assert_eq!(header(&response, "cache-control"), Some("no-store"));
+		assert_eq!(header(&response, "etag"), None);
+		assert_eq!(header(&response, SEARCH_REASON_HEADER), None);
+
+		let response = skipped(Some("query_too_short"));
+
+		assert_eq!(header(&response, "cache-control"), Some("no-store"));
+		assert_eq!(
+			header(&response, SEARCH_REASON_HEADER),
+			Some("query_too_short")
+		);
+	}
+}
@@ -1,86 +1,169 @@
 use axum::{extract::Path, Extension};
 use axum_jsonschema::Json;
+use futures::FutureExt;
 use http::StatusCode;
 
 use crate::{
-	config::{ConfigExt, Db},
+	audit,
+	config::{Config, ConfigExt, Db},
 	types::{ErrorResponse, Name, UpdateUsernamePayload},
+	utils::secure_eq,
+	validation::{ensure_address_below_limit, verification_error},
 	verify,
 };
 
-#[allow(dependency_on_unit_never_type_fallback)]
 pub async fn update_record(
 	Path(username): Path<String>,
 	Extension(config): ConfigExt,
 	Extension(db): Extension<Db>,
 	Json(payload): Json<UpdateUsernamePayload>,
+) -> Result<StatusCode, ErrorResponse> {
+	let mutation = audit::Mutation::new(
+		"update",
+		username.clone(),
+		Some(payload.address.to_checksum(None)),
+		payload.nullifier_hash.clone(),
+	);
+
+	let outcome = update(&username, &config, &db, &payload).await;
+	mutation.record(&outcome);
+
+	outcome
+}
+
+#[allow(dependency_on_unit_never_type_fallback)]
+async fn update(
+	username: &str,
+	config: &Config,
+	db: &Db,
+	payload: &UpdateUsernamePayload,
 ) -> Result<StatusCode, ErrorResponse> {
 	let Some(record) = sqlx::query_as!(Name, "SELECT * FROM names WHERE username = $1", username)
 		.fetch_optional(&db.read_write)
+		.inspect(db.latency.read_write.timer())
 		.await?
 	else {
 		return Err(ErrorResponse::not_found("Username not found".to_string()));
 	};
 
-	if record.nullifier_hash != payload.nullifier_hash {
+	if !secure_eq(&record.nullifier_hash, &payload.nullifier_hash) {
 		return Err(ErrorResponse::unauthorized(
 			"You can't update this name".to_string(),
 		));
 	}
 
-	match verify::dev_portal_verify_proof(
+	let pubkey = payload.pubkey.as_ref().and_then(Option::as_ref);
+
+	if pubkey.is_some_and(|p| !p.is_valid()) {
+		return Err(ErrorResponse::validation_error(
+			"Public key coordinates must be 32 bytes each".to_string(),
+		));
+	}
+
+	verify::dev_portal_verify_proof(
 		payload.into_proof(),
 		config.wld_app_id.to_string(),
 		"username",
 		(
-			&username,
+			username,
 			payload.address.to_checksum(None),
 			payload
 				.profile_picture_url
 				.as_ref()
 				.map(ToString::to_string)
 				.unwrap_or_default(),
+			payload
+				.contenthash
+				.as_ref()
+				.and_then(Option::as_ref)
+				.map(ToString::to_string)
+				.unwrap_or_default(),
+			pubkey
+				.map(|p| format!("{}{}", p.x, p.y))
+				.unwrap_or_default(),
 		),
 		config.developer_portal_url.clone(),
+		&config.developer_portal_verify_path,
+		config.verify_options(),
 	)
 	.await
-	{
-		Ok(()) => {},
-		Err(verify::Error::Verification(e)) => {
-			tracing::error!(
-				"Update Record Verification Error: {}, Payload: {:?}",
-				e.detail,
-				payload
-			);
-			return Err(ErrorResponse::validation_error(e.detail));
-		},
-		Err(e) => {
-			tracing::error!(
-				"Update Record Server Error: {}, Payload: {:?}",
-				e.to_string(),
-				payload
-			);
-			return Err(ErrorResponse::server_error(
-				"Failed to verify World ID proof".to_string(),
-			));
-		},
-	};
+	.map_err(|e| verification_error("Update Record", e))?;
+
+	let mut tx = db.read_write.begin().await?;
+
+	let new_address = payload.address.to_checksum(None);
+	ensure_address_below_limit(
+		&mut tx,
+		&new_address,
+		address_limit(
+			&record.address,
+			&new_address,
+			config.max_usernames_per_address,
+		),
+	)
+	.await?;
 
+	// Clients that predate the content hash or public key don't send them, which must leave the stored ones alone.
 	sqlx::query!(
-		"UPDATE names SET address = $1, profile_picture_url = $2 WHERE username = $3",
-		payload.address.to_checksum(None),
+		"UPDATE names SET address = $1, profile_picture_url = $2, contenthash = CASE WHEN $3 THEN $4 ELSE contenthash END, pubkey_x = CASE WHEN $5 THEN $6 ELSE pubkey_x END, pubkey_y = CASE WHEN $5 THEN $7 ELSE pubkey_y END, updated_at = NOW() WHERE username = $8",
+		new_address,
 		payload
 			.profile_picture_url
 			.as_ref()
 			.map(ToString::to_string),
+		payload.contenthash.is_some(),
+		payload
+			.contenthash
+			.as_ref()
+			.and_then(Option::as_ref)
+			.map(|c| c.to_vec()),
+		payload.pubkey.is_some(),
+		pubkey.map(|p| p.x.to_vec()),
+		pubkey.map(|p| p.y.to_vec()),
 		username
 	)
-	.execute(&db.read_write)
+	.execute(&mut *tx)
 	.await?;
 
+	tx.commit().await?;
+
 	Ok(StatusCode::OK)
 }
 
+/// The per-address limit to enforce on an update. Only moving a name to another address can take that address over it.
+fn address_limit(current_address: &str, new_address: &str, limit: Option<i64>) -> Option<i64> {
+	if new_address == current_address {
+		return None;
+	}
+
+	limit
+}
+
 pub fn docs(op: aide::transform::TransformOperation) -> aide::transform::TransformOperation {
 	op.description("Update the details attached to a World App username.")
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const CURRENT: &str = "0x000000000000000000000000000000000000dEaD";
+	const OTHER: &str = "0x0000000000000000000000000000000000000001";
+
+	#[test]
+	fn moving_to_another_address_enforces_the_limit() {
+		assert_eq!(address_limit(CURRENT, OTHER, Some(3)), Some(3));
+	}
+
+	#[test]
+	fn keeping_the_address_skips_the_limit() {
+		// An address already at the limit can still update the names it holds.
+		assert_eq!(address_limit(CURRENT, CURRENT, Some(3)), None);
+	}
+
+	#[test]
+	fn unset_limit_is_never_enforced() {
+		assert_eq!(address_limit(CURRENT, OTHER, None), None);
+		assert_eq!(address_limit(CURRENT, CURRENT, None), None);
+	}
+}
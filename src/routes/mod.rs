@@ -1,16 +1,18 @@
 use aide::axum::{routing::get_with, ApiRouter};
 
-mod api;
+pub(crate) mod api;
 mod docs;
 mod health;
 mod system;
 
+use crate::config::Config;
+
 use health::{docs as health_docs, health};
 
-pub fn handler() -> ApiRouter {
+pub fn handler(config: &Config) -> ApiRouter {
 	ApiRouter::new()
 		.merge(docs::handler())
 		.merge(system::handler())
 		.api_route("/health", get_with(health, health_docs))
-		.nest("/api", api::handler())
+		.nest("/api", api::handler(config))
 }
@@ -0,0 +1,179 @@
+use axum::{
+	body::{to_bytes, Body},
+	extract::Request,
+	middleware::Next,
+	response::Response,
+	Extension,
+};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use http::header;
+use sha2::Sha256;
+
+use crate::{config::ConfigExt, types::ErrorResponse, utils::secure_eq};
+
+/// Require the configured system API key as a bearer token. System routes are disabled when no key is configured.
+pub async fn system_auth(
+	Extension(config): ConfigExt,
+	request: Request,
+	next: Next,
+) -> Result<Response, ErrorResponse> {
+	let Some(api_key) = config.system_api_key.as_deref() else {
+		return Err(ErrorResponse::unauthorized(
+			"System routes are disabled".to_string(),
+		));
+	};
+
+	let provided = request
+		.headers()
+		.get(header::AUTHORIZATION)
+		.and_then(|value| value.to_str().ok())
+		.and_then(|value| value.strip_prefix("Bearer "));
+
+	if !provided.is_some_and(|provided| secure_eq(provided, api_key)) {
+		return Err(ErrorResponse::unauthorized(
+			"Invalid system API key".to_string(),
+		));
+	}
+
+	Ok(next.run(request).await)
+}
+
+/// Header carrying the Unix time a system request was signed at.
+const TIMESTAMP_HEADER: &str = "x-signature-timestamp";
+/// Header carrying the hex-encoded HMAC-SHA256 of `{timestamp}.{body}`.
+const SIGNATURE_HEADER: &str = "x-signature";
+/// How far, in seconds, a signature's timestamp may be from now. Bounds how long a captured request can be replayed.
+const MAX_SIGNATURE_SKEW: i64 = 300;
+
+/// Require an HMAC signature over the request body, made with the configured system HMAC secret.
+/// Signed routes are disabled when no secret is configured.
+pub async fn system_hmac(
+	Extension(config): ConfigExt,
+	request: Request,
+	next: Next,
+) -> Result<Response, ErrorResponse> {
+	let Some(secret) = config.system_hmac_secret.as_deref() else {
+		return Err(ErrorResponse::unauthorized(
+			"Signed system routes are disabled".to_string(),
+		));
+	};
+
+	let (parts, body) = request.into_parts();
+
+	let header_value = |name: &str| {
+		parts
+			.headers
+			.get(name)
+			.and_then(|value| value.to_str().ok())
+	};
+
+	let Some(timestamp) = header_value(TIMESTAMP_HEADER).and_then(|t| t.parse::<i64>().ok()) else {
+		return Err(ErrorResponse::unauthorized(
+			"Missing or invalid signature timestamp".to_string(),
+		));
+	};
+
+	if !is_fresh(timestamp, Utc::now().timestamp()) {
+		return Err(ErrorResponse::unauthorized(
+			"Signature timestamp is too old or in the future".to_string(),
+		));
+	}
+
+	let Some(signature) = header_value(SIGNATURE_HEADER).and_then(|s| hex::decode(s).ok()) else {
+		return Err(ErrorResponse::unauthorized(
+			"Missing or invalid signature".to_string(),
+		));
+	};
+
+	let body = to_bytes(body, config.max_body_size)
+		.await
+		.map_err(|_| ErrorResponse::payload_too_large("Request body is too large".to_string()))?;
+
+	if !verify_signature(secret, timestamp, &body, &signature) {
+		return Err(ErrorResponse::unauthorized("Invalid signature".to_string()));
+	}
+
+	Ok(next.run(Request::from_parts(parts, Body::from(body))).await)
+}
+
+const fn is_fresh(timestamp: i64, now: i64) -> bool {
+	timestamp.abs_diff(now) <= MAX_SIGNATURE_SKEW.unsigned_abs()
+}
+
+/// Check, in constant time, that `signature` is the HMAC-SHA256 of `{timestamp}.{body}` under `secret`.
+fn verify_signature(secret: &str, timestamp: i64, body: &[u8], signature: &[u8]) -> bool {
+	let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret.as_bytes()) else {
+		return false;
+	};
+
+	mac.update(format!("{timestamp}.").as_bytes());
+	mac.update(body);
+
+	mac.verify_slice(signature).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn sign(secret: &str, timestamp: i64, body: &[u8]) -> Vec<u8> {
+		let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+		mac.update(format!("{timestamp}.").as_bytes());
+		mac.update(body);
+		mac.finalize().into_bytes().to_vec()
+	}
+
+	#[test]
+	fn accepts_a_valid_signature() {
+		let signature = sign("secret", 1_700_000_000, b"{\"names\":[]}");
+
+		assert!(verify_signature(
+			"secret",
+			1_700_000_000,
+			b"{\"names\":[]}",
+			&signature
+		));
+	}
+
+	#[test]
+	fn rejects_tampered_requests() {
+		let signature = sign("secret", 1_700_000_000, b"{\"names\":[]}");
+
+		assert!(!verify_signature(
+			"secret",
+			1_700_000_000,
+			b"{\"names\":[{}]}",
+			&signature
+		));
+		assert!(!verify_signature(
+			"secret",
+			1_700_000_001,
+			b"{\"names\":[]}",
+			&signature
+		));
+		assert!(!verify_signature(
+			"other",
+			1_700_000_000,
+			b"{\"names\":[]}",
+			&signature
+		));
+		assert!(!verify_signature(
+			"secret",
+			1_700_000_000,
+			b"{\"names\":[]}",
+			&signature[..16]
+		));
+	}
+
+	#[test]
+	fn rejects_stale_timestamps() {
+		let now = 1_700_000_000;
+
+		assert!(is_fresh(now, now));
+		assert!(is_fresh(now - MAX_SIGNATURE_SKEW, now));
+		assert!(is_fresh(now + MAX_SIGNATURE_SKEW, now));
+		assert!(!is_fresh(now - MAX_SIGNATURE_SKEW - 1, now));
+		assert!(!is_fresh(now + MAX_SIGNATURE_SKEW + 1, now));
+	}
+}
@@ -0,0 +1,69 @@
+use axum::Extension;
+use http::StatusCode;
+
+use crate::{
+	config::{ConfigExt, Db},
+	utils::name_nodes,
+};
+
+/// How many names have their nodes computed per round-trip.
+const BACKFILL_BATCH_SIZE: i64 = 1000;
+
+#[allow(clippy::unused_async)]
+pub async fn backfill_nodes(
+	Extension(config): ConfigExt,
+	Extension(db): Extension<Db>,
+) -> StatusCode {
+	// Walking the whole table can take a while, so it runs in the background instead of holding the request open.
+	tokio::spawn(async move {
+		match backfill(&db, &config.ens_domains).await {
+			Ok(inserted) => tracing::info!(inserted, "Backfilled ENS nodes"),
+			Err(e) => tracing::error!(error = %e, "Failed to backfill ENS nodes"),
+		}
+	});
+
+	StatusCode::ACCEPTED
+}
+
+/// Compute the node of every name under every ENS domain, skipping the ones that are already stored.
+async fn backfill(db: &Db, ens_domains: &[String]) -> Result<u64, sqlx::Error> {
+	let mut inserted = 0;
+	let mut after = String::new();
+
+	loop {
+		let usernames = sqlx::query_scalar!(
+			"SELECT username FROM names WHERE username > $1 ORDER BY username LIMIT $2",
+			&after,
+			BACKFILL_BATCH_SIZE
+		)
+		.fetch_all(&db.read_write)
+		.await?;
+
+		let Some(last) = usernames.last() else {
+			return Ok(inserted);
+		};
+		after.clone_from(last);
+
+		let (nodes, owners): (Vec<_>, Vec<_>) = usernames
+			.iter()
+			.flat_map(|username| {
+				name_nodes(username, ens_domains)
+					.into_iter()
+					.map(move |node| (node, username.clone()))
+			})
+			.unzip();
+
+		inserted += sqlx::query!(
+			"INSERT INTO name_nodes (node, username) SELECT * FROM UNNEST($1::bytea[], $2::varchar[]) ON CONFLICT DO NOTHING",
+			&nodes,
+			&owners
+		)
+		.execute(&db.read_write)
+		.await?
+		.rows_affected();
+	}
+}
+
+pub fn docs(op: aide::transform::TransformOperation) -> aide::transform::TransformOperation {
+	op.description("Start computing the ENS node of every name under every configured ENS domain, e.g. after a domain is added. Runs in the background and is safe to repeat. Requires the system API key.")
+}
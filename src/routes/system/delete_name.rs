@@ -0,0 +1,230 @@
+use axum::{extract::Path, Extension};
+use axum_jsonschema::Json;
+use http::StatusCode;
+use schemars::JsonSchema;
+
+use crate::{
+	audit,
+	config::Db,
+	types::{Address, ErrorResponse},
+};
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct DeleteAddressPayload {
+	/// The wallet address whose usernames should be deleted
+	address: Address,
+}
+
+#[derive(Debug, serde::Serialize, JsonSchema)]
+pub struct DeleteAddressResponse {
+	/// The usernames that were deleted
+	usernames: Vec<String>,
+}
+
+pub async fn delete_name(
+	Extension(db): Extension<Db>,
+	Path(username): Path<String>,
+) -> Result<StatusCode, ErrorResponse> {
+	let mut tx = db.read_write.begin().await?;
+
+	// Redirects to the name would otherwise point at nothing (and block the delete).
+	sqlx::query!("DELETE FROM old_names WHERE new_username = $1", &username)
+		.execute(&mut *tx)
+		.await?;
+
+	let Some(deleted) = sqlx::query!(
+		"DELETE FROM names WHERE username = $1 RETURNING address, nullifier_hash",
+		&username
+	)
+	.fetch_optional(&mut *tx)
+	.await?
+	else {
+		return Err(ErrorResponse::not_found("Username not found".to_string()));
+	};
+
+	sqlx::query!(
+		"INSERT INTO deleted_names (username) VALUES ($1) ON CONFLICT DO NOTHING",
+		&username
+	)
+	.execute(&mut *tx)
+	.await?;
+
+	tx.commit().await?;
+
+	let outcome = Ok(StatusCode::NO_CONTENT);
+	audit::Mutation::new(
+		"moderation_delete",
+		username,
+		Some(deleted.address),
+		deleted.nullifier_hash,
+	)
+	.record(&outcome);
+
+	outcome
+}
+
+pub async fn delete_address(
+	Extension(db): Extension<Db>,
+	Json(payload): Json<DeleteAddressPayload>,
+) -> Result<Json<DeleteAddressResponse>, ErrorResponse> {
+	let address = payload.address.to_checksum(None);
+	let mut tx = db.read_write.begin().await?;
+
+	sqlx::query!(
+		"DELETE FROM old_names WHERE new_username IN (SELECT username FROM names WHERE address = $1)",
+		&address
+	)
+	.execute(&mut *tx)
+	.await?;
+
+	let deleted = sqlx::query!(
+		"DELETE FROM names WHERE address = $1 RETURNING username, nullifier_hash",
+		&address
+	)
+	.fetch_all(&mut *tx)
+	.await?;
+
+	let usernames = deleted
+		.iter()
+		.map(|name| name.username.clone())
+		.collect::<Vec<_>>();
+
+	sqlx::query!(
+		"INSERT INTO deleted_names (username) SELECT * FROM UNNEST($1::varchar[]) ON CONFLICT DO NOTHING",
+		&usernames
+	)
+	.execute(&mut *tx)
+	.await?;
+
+	tx.commit().await?;
+
+	for name in deleted {
+		audit::Mutation::new(
+			"support_delete",
+			name.username,
+			Some(address.clone()),
+			name.nullifier_hash,
+		)
+		.record(&Ok(StatusCode::OK));
+	}
+
+	Ok(Json(DeleteAddressResponse { usernames }))
+}
+
+pub fn delete_address_docs(
+	op: aide::transform::TransformOperation,
+) -> aide::transform::TransformOperation {
+	op.description("Immediately delete every username owned by a wallet address, removing redirects to them and leaving tombstones so lookups return 410 and the names can't be registered again. Returns the deleted usernames. Requires the system API key.")
+}
+
+pub fn docs(op: aide::transform::TransformOperation) -> aide::transform::TransformOperation {
+	op.description("Force-delete a username for moderation, removing redirects to it and leaving a tombstone so lookups return 410 and the name can't be registered again. Requires the system API key.")
+		.response::<404, ErrorResponse>()
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+
+	use sqlx::PgPool;
+
+	use super::*;
+	use crate::{
+		latency::Latency,
+		routes::api::v1::query_single::{lookup, Lookup},
+		types::Name,
+	};
+
+	const ADDRESS: &str = "0x000000000000000000000000000000000000dEaD";
+
+	fn db(pool: PgPool) -> Db {
+		Db {
+			read_only: pool.clone(),
+			read_write: pool,
+			latency: Arc::new(Latency::default()),
+		}
+	}
+
+	async fn register(db: &Db, username: &str) {
+		let now = chrono::Utc::now().naive_utc();
+		Name {
+			address: ADDRESS.to_string(),
+			username: username.to_string(),
+			profile_picture_url: None,
+			nullifier_hash: format!("0x{username}"),
+			verification_level: "orb".to_string(),
+			created_at: now,
+			updated_at: now,
+			contenthash: None,
+			pubkey_x: None,
+			pubkey_y: None,
+		}
+		.insert_with(&db.read_write, &["world.id".to_string()])
+		.await
+		.unwrap();
+	}
+
+	#[sqlx::test]
+	#[ignore = "needs a Postgres database at DATABASE_URL"]
+	async fn deleted_names_are_gone_from_lookups(pool: PgPool) {
+		let db = db(pool);
+		register(&db, "alice").await;
+		assert!(matches!(
+			lookup(&db, "alice").await.unwrap(),
+			Lookup::Found { .. }
+		));
+
+		let status = delete_name(Extension(db.clone()), Path("alice".to_string()))
+			.await
+			.unwrap();
+
+		assert_eq!(status, StatusCode::NO_CONTENT);
+		assert!(matches!(
+			lookup(&db, "alice").await.unwrap(),
+			Lookup::Deleted
+		));
+		assert!(matches!(
+			lookup(&db, ADDRESS).await.unwrap(),
+			Lookup::NotFound
+		));
+	}
+
+	#[sqlx::test]
+	#[ignore = "needs a Postgres database at DATABASE_URL"]
+	async fn deletes_every_name_of_an_address(pool: PgPool) {
+		let db = db(pool);
+		register(&db, "alice").await;
+		register(&db, "alicia").await;
+
+		let payload = DeleteAddressPayload {
+			address: Address(ADDRESS.parse().unwrap()),
+		};
+		let Json(response) = delete_address(Extension(db.clone()), Json(payload))
+			.await
+			.unwrap();
+
+		let mut usernames = response.usernames;
+		usernames.sort();
+		assert_eq!(usernames, ["alice", "alicia"]);
+		for username in ["alice", "alicia"] {
+			assert!(matches!(
+				lookup(&db, username).await.unwrap(),
+				Lookup::Deleted
+			));
+		}
+		assert!(matches!(
+			lookup(&db, ADDRESS).await.unwrap(),
+			Lookup::NotFound
+		));
+	}
+
+	#[sqlx::test]
+	#[ignore = "needs a Postgres database at DATABASE_URL"]
+	async fn deleting_an_unknown_name_is_not_found(pool: PgPool) {
+		let error = delete_name(Extension(db(pool)), Path("alice".to_string()))
+			.await
+			.unwrap_err();
+
+		assert_eq!(error.status(), StatusCode::NOT_FOUND);
+	}
+}
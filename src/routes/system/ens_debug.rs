@@ -0,0 +1,119 @@
+use axum::{extract::Path, Extension};
+use axum_jsonschema::Json;
+use futures::FutureExt;
+use schemars::JsonSchema;
+
+use crate::{
+	config::{Config, ConfigExt, Db},
+	routes::api::v1::ens_gateway::resolve_method,
+	types::{ErrorResponse, Method, Name},
+	utils::{namehash, normalize_name},
+};
+
+#[derive(Debug, serde::Serialize, JsonSchema)]
+pub struct EnsDebugResponse {
+	/// The queried ENS name
+	name: String,
+	/// 0x-prefixed namehash (node) of the queried name
+	namehash: String,
+	/// The username the name maps to, if it is under a configured ENS domain
+	username: Option<String>,
+	/// Whether the username is registered
+	exists: bool,
+	/// What `addr` would resolve to
+	addr: Option<MethodPreview>,
+	/// What the `avatar` text record would resolve to
+	avatar: Option<MethodPreview>,
+	/// What the `org.worldcoin.level` text record would resolve to
+	verification_level: Option<MethodPreview>,
+	/// What `contenthash` would resolve to
+	contenthash: Option<MethodPreview>,
+	/// What `pubkey` would resolve to
+	pubkey: Option<MethodPreview>,
+}
+
+/// What the gateway would answer for a method, before signing.
+#[derive(Debug, serde::Serialize, JsonSchema)]
+pub struct MethodPreview {
+	/// 0x-prefixed ABI-encoded result, if the method resolves
+	data: Option<String>,
+	/// The gateway's error code, if it doesn't
+	error: Option<String>,
+}
+
+pub async fn ens_debug(
+	Extension(config): ConfigExt,
+	Extension(db): Extension<Db>,
+	Path(name): Path<String>,
+) -> Result<Json<EnsDebugResponse>, ErrorResponse> {
+	// Looked up like the gateway does: the username ignores stray characters, the node is computed over the name as sent.
+	let username = config
+		.strip_ens_domain(&normalize_name(&name))
+		.map(ToString::to_string);
+
+	let record = match &username {
+		Some(username) => {
+			sqlx::query_as!(Name, "SELECT * FROM names WHERE username = $1", username)
+				.fetch_optional(&db.read_only)
+				.inspect(db.latency.read_only.timer())
+				.await?
+		},
+		None => None,
+	};
+
+	let node = namehash(&name).to_vec();
+	let preview = |method: Method| {
+		record
+			.as_ref()
+			.map(|record| preview_method(&config, &name, record, method))
+	};
+
+	Ok(Json(EnsDebugResponse {
+		namehash: to_hex(&node),
+		exists: record.is_some(),
+		addr: preview(Method::Addr(node.clone())),
+		avatar: preview(Method::Text(node.clone(), "avatar".to_string())),
+		verification_level: preview(Method::Text(
+			node.clone(),
+			"org.worldcoin.level".to_string(),
+		)),
+		contenthash: preview(Method::ContentHash(node.clone())),
+		pubkey: preview(Method::PubKey(node)),
+		username,
+		name,
+	}))
+}
+
+fn preview_method(config: &Config, name: &str, record: &Name, method: Method) -> MethodPreview {
+	match resolve_method(config, name, record, method) {
+		Ok(data) => MethodPreview {
+			data: Some(to_hex(&data)),
+			error: None,
+		},
+		Err(e) => MethodPreview {
+			data: None,
+			error: e.code,
+		},
+	}
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+	format!("0x{}", hex::encode(bytes))
+}
+
+pub fn docs(op: aide::transform::TransformOperation) -> aide::transform::TransformOperation {
+	op.description("Preview how the ENS gateway would resolve a name, without signing. Each method shows the ABI-encoded result the gateway would sign, or its error code. Requires the system API key.")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn reports_the_namehash_as_0x_prefixed_hex() {
+		assert_eq!(
+			to_hex(&namehash("foo.eth")),
+			"0xde9b09fd7c5f901e23a3f19fecc54828e9c848539801e86591bd9801b019f84f"
+		);
+	}
+}
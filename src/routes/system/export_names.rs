@@ -0,0 +1,162 @@
+use axum::{
+	body::Body,
+	response::{IntoResponse, Response},
+	Extension,
+};
+use futures::{stream, Stream, StreamExt};
+use http::header;
+use tokio::sync::mpsc;
+
+use crate::{
+	config::Db,
+	types::{NameSearch, UsernameRecord},
+};
+
+/// How many serialized records may wait for the client before reading from the database pauses.
+const EXPORT_BUFFER: usize = 256;
+
+#[allow(clippy::unused_async)]
+pub async fn export_names(Extension(db): Extension<Db>) -> Response {
+	let (tx, rx) = mpsc::channel(EXPORT_BUFFER);
+
+	// Rows are streamed from the database and written out as they arrive, so memory stays bounded regardless of table size.
+	tokio::spawn(async move {
+		let rows = sqlx::query_as!(
+			NameSearch,
+			"SELECT username, address, profile_picture_url FROM names ORDER BY username"
+		)
+		.fetch(&db.read_only);
+
+		write_lines(rows, tx).await;
+	});
+
+	(
+		[(header::CONTENT_TYPE, "application/x-ndjson")],
+		ndjson_body(rx),
+	)
+		.into_response()
+}
+
+/// Serialize each row as an NDJSON line. Rows that can't be turned into a record are logged and skipped, so one bad row
+/// doesn't cut the export short, while a database error ends the stream.
+async fn write_lines(
+	mut rows: impl Stream<Item = Result<NameSearch, sqlx::Error>> + Unpin,
+	tx: mpsc::Sender<Result<String, sqlx::Error>>,
+) {
+	while let Some(row) = rows.next().await {
+		let line = match row.map(UsernameRecord::try_from) {
+			Ok(Ok(record)) => {
+				let mut line = serde_json::to_string(&record).expect("records always serialize");
+				line.push('\n');
+				Ok(line)
+			},
+			Ok(Err(e)) => {
+				tracing::warn!("Skipping name in export: {e}");
+				continue;
+			},
+			Err(e) => Err(e),
+		};
+
+		// Stop reading once the client has gone away.
+		if tx.send(line).await.is_err() {
+			break;
+		}
+	}
+}
+
+fn ndjson_body(rx: mpsc::Receiver<Result<String, sqlx::Error>>) -> Body {
+	Body::from_stream(stream::unfold(rx, |mut rx| async move {
+		rx.recv().await.map(|line| (line, rx))
+	}))
+}
+
+pub fn docs(op: aide::transform::TransformOperation) -> aide::transform::TransformOperation {
+	op.description("Export every username as newline-delimited JSON records, streamed as they are read. Requires the system API key.")
+}
+
+#[cfg(test)]
+mod tests {
+	use axum::body::to_bytes;
+
+	use super::*;
+
+	fn name(username: String, address: &str) -> Result<NameSearch, sqlx::Error> {
+		Ok(NameSearch {
+			username,
+			address: address.to_string(),
+			profile_picture_url: None,
+		})
+	}
+
+	fn names(count: usize) -> Vec<Result<NameSearch, sqlx::Error>> {
+		(0..count)
+			.map(|i| {
+				name(
+					format!("user{i}"),
+					"0x0000000000000000000000000000000000000001",
+				)
+			})
+			.collect()
+	}
+
+	async fn export(rows: Vec<Result<NameSearch, sqlx::Error>>) -> String {
+		let (tx, rx) = mpsc::channel(EXPORT_BUFFER);
+		tokio::spawn(write_lines(stream::iter(rows), tx));
+
+		String::from_utf8(
+			to_bytes(ndjson_body(rx), usize::MAX)
+				.await
+				.unwrap()
+				.to_vec(),
+		)
+		.unwrap()
+	}
+
+	#[tokio::test]
+	async fn streams_more_rows_than_the_buffer_holds() {
+		let (tx, rx) = mpsc::channel(EXPORT_BUFFER);
+		let writer = tokio::spawn(write_lines(stream::iter(names(EXPORT_BUFFER * 4)), tx));
+
+		// With nobody reading, the writer stalls once the buffer is full instead of reading every row.
+		for _ in 0..100 {
+			tokio::task::yield_now().await;
+		}
+		assert!(!writer.is_finished());
+
+		let body = to_bytes(ndjson_body(rx), usize::MAX).await.unwrap();
+		assert_eq!(
+			body.iter().filter(|&&b| b == b'\n').count(),
+			EXPORT_BUFFER * 4
+		);
+		assert!(writer.await.is_ok());
+	}
+
+	#[tokio::test]
+	async fn skips_rows_that_are_not_valid_records() {
+		let mut rows = names(2);
+		rows.insert(1, name("broken".to_string(), "not-an-address"));
+
+		let body = export(rows).await;
+		let usernames: Vec<String> = body
+			.lines()
+			.map(|line| {
+				serde_json::from_str::<UsernameRecord>(line)
+					.unwrap()
+					.username
+			})
+			.collect();
+
+		assert_eq!(usernames, ["user0", "user1"]);
+	}
+
+	#[tokio::test]
+	async fn ends_the_stream_on_a_database_error() {
+		let mut rows = names(2);
+		rows.insert(1, Err(sqlx::Error::RowNotFound));
+
+		let (tx, rx) = mpsc::channel(EXPORT_BUFFER);
+		tokio::spawn(write_lines(stream::iter(rows), tx));
+
+		assert!(to_bytes(ndjson_body(rx), usize::MAX).await.is_err());
+	}
+}
@@ -0,0 +1,127 @@
+use aide::axum::{
+	routing::{delete_with, get, get_with, post_with},
+	ApiRouter,
+};
+use axum::{middleware, Extension};
+use axum_jsonschema::Json;
+use schemars::JsonSchema;
+
+use crate::{config::Db, latency::Samples};
+
+pub(crate) mod auth;
+mod backfill_nodes;
+mod delete_name;
+mod ens_debug;
+mod export_names;
+mod register_batch;
+mod schema;
+
+use backfill_nodes::{backfill_nodes, docs as backfill_nodes_docs};
+use delete_name::{delete_address, delete_address_docs, delete_name, docs as delete_name_docs};
+use ens_debug::{docs as ens_debug_docs, ens_debug};
+use export_names::{docs as export_names_docs, export_names};
+use register_batch::{docs as register_batch_docs, register_batch};
+use schema::{docs as schema_docs, schema};
+
+pub fn handler() -> ApiRouter {
+	// Migrations push whole batches of names, so they're authenticated by a signature over the body instead of a bearer key.
+	let signed = ApiRouter::new()
+		.api_route(
+			"/system/register-batch",
+			post_with(register_batch, register_batch_docs),
+		)
+		.route_layer(middleware::from_fn(auth::system_hmac));
+
+	ApiRouter::new()
+		.api_route(
+			"/system/backfill-nodes",
+			post_with(backfill_nodes, backfill_nodes_docs),
+		)
+		.api_route(
+			"/system/ens/debug/:name",
+			get_with(ens_debug, ens_debug_docs),
+		)
+		.api_route(
+			"/system/delete",
+			post_with(delete_address, delete_address_docs),
+		)
+		.api_route(
+			"/system/export/names",
+			get_with(export_names, export_names_docs),
+		)
+		.api_route(
+			"/system/names/:username",
+			delete_with(delete_name, delete_name_docs),
+		)
+		.api_route("/system/latency", get(latency))
+		.route_layer(middleware::from_fn(auth::system_auth))
+		.merge(signed)
+		.api_route("/", get(root))
+		.api_route("/system/schema/:type", get_with(schema, schema_docs))
+}
+
+#[derive(Debug, serde::Serialize, JsonSchema)]
+pub struct AppVersion {
+	/// Current version of the application
+	semver: String,
+	/// Commit hash of the current build (if available)
+	rev: Option<String>,
+	/// The time the application was compiled at
+	compile_time: String,
+}
+
+#[derive(Debug, serde::Serialize, JsonSchema)]
+pub struct RootResponse {
+	/// Relative URL to Swagger UI
+	pub docs_url: String,
+	/// Relative URL to `OpenAPI` specification
+	pub openapi_url: String,
+	/// Application version
+	pub version: AppVersion,
+}
+
+#[allow(clippy::unused_async)]
+pub async fn root() -> Json<RootResponse> {
+	Json(RootResponse {
+		docs_url: "/docs".to_string(),
+		openapi_url: "/openapi.json".to_string(),
+		version: AppVersion {
+			semver: env!("CARGO_PKG_VERSION").to_string(),
+			compile_time: env!("STATIC_BUILD_DATE").to_string(),
+			rev: option_env!("GIT_REV").map(ToString::to_string),
+		},
+	})
+}
+
+#[derive(Debug, serde::Serialize, JsonSchema)]
+pub struct Percentiles {
+	/// Median query latency, in microseconds (if any samples were recorded)
+	p50_micros: Option<u64>,
+	/// 95th percentile query latency, in microseconds (if any samples were recorded)
+	p95_micros: Option<u64>,
+}
+
+impl From<&Samples> for Percentiles {
+	fn from(samples: &Samples) -> Self {
+		Self {
+			p50_micros: samples.percentile(50),
+			p95_micros: samples.percentile(95),
+		}
+	}
+}
+
+#[derive(Debug, serde::Serialize, JsonSchema)]
+pub struct LatencyResponse {
+	/// Latency of the read-only database pool
+	read_only: Percentiles,
+	/// Latency of the read-write database pool
+	read_write: Percentiles,
+}
+
+#[allow(clippy::unused_async)]
+pub async fn latency(Extension(db): Extension<Db>) -> Json<LatencyResponse> {
+	Json(LatencyResponse {
+		read_only: Percentiles::from(&db.latency.read_only),
+		read_write: Percentiles::from(&db.latency.read_write),
+	})
+}
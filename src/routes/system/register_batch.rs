@@ -0,0 +1,255 @@
+use std::collections::HashSet;
+
+use axum::Extension;
+use axum_jsonschema::Json;
+use schemars::JsonSchema;
+use sqlx::{Connection, PgConnection};
+use url::Url;
+
+use crate::{
+	blocklist::{Blocklist, BlocklistExt},
+	config::{Config, ConfigExt, Db},
+	types::{Address, ErrorResponse, Name, VerificationLevel},
+	validation::{address_at_limit, username_failures, ADDRESS_LIMIT_REACHED},
+};
+
+const USERNAME_TAKEN: &str = "Username is already taken";
+const WORLD_ID_TAKEN: &str = "This World ID has already registered a username.";
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct BatchName {
+	/// The username to register
+	username: String,
+	/// The user's wallet address
+	address: Address,
+	/// The user's profile picture URL
+	profile_picture_url: Option<Url>,
+	/// 0x-prefixed hex string of the World ID nullifier hash
+	nullifier_hash: String,
+	/// World ID verification level the user holds
+	verification_level: VerificationLevel,
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct RegisterBatchPayload {
+	/// Pre-verified names to register
+	names: Vec<BatchName>,
+}
+
+#[derive(Debug, serde::Serialize, JsonSchema)]
+pub struct BatchResult {
+	/// The username this result refers to
+	username: String,
+	/// Whether the username was registered
+	registered: bool,
+	/// Why the username wasn't registered, if it wasn't
+	error: Option<String>,
+}
+
+impl BatchResult {
+	const fn registered(username: String) -> Self {
+		Self {
+			username,
+			registered: true,
+			error: None,
+		}
+	}
+
+	fn failed(username: String, error: String) -> Self {
+		Self {
+			username,
+			registered: false,
+			error: Some(error),
+		}
+	}
+}
+
+pub async fn register_batch(
+	Extension(config): ConfigExt,
+	Extension(db): Extension<Db>,
+	Extension(blocklist): BlocklistExt,
+	Json(payload): Json<RegisterBatchPayload>,
+) -> Result<Json<Vec<BatchResult>>, ErrorResponse> {
+	if payload.names.len() > config.register_batch_max_size {
+		return Err(ErrorResponse::payload_too_large(format!(
+			"At most {} names can be registered at once",
+			config.register_batch_max_size
+		)));
+	}
+
+	let failures = precheck(&payload.names, &blocklist);
+
+	let mut tx = db.read_write.begin().await?;
+	let mut results = Vec::with_capacity(payload.names.len());
+
+	for (entry, failure) in payload.names.into_iter().zip(failures) {
+		if let Some(failure) = failure {
+			results.push(BatchResult::failed(entry.username, failure));
+			continue;
+		}
+
+		// Each name gets its own savepoint, so a database error on one only rolls back that name instead of the whole batch.
+		let username = entry.username.clone();
+		let mut savepoint = Connection::begin(&mut *tx).await?;
+
+		let result = match register_entry(&mut savepoint, &config, entry).await {
+			Ok(result) => {
+				savepoint.commit().await?;
+				result
+			},
+			Err(e) => {
+				tracing::error!("Failed to register {username:?} in batch: {e}");
+				savepoint.rollback().await?;
+				BatchResult::failed(username, "Failed to register username".to_string())
+			},
+		};
+
+		results.push(result);
+	}
+
+	tx.commit().await?;
+
+	Ok(Json(results))
+}
+
+/// Find why each name can't be registered, as far as can be told without the database: format and blocklist rules,
+/// and usernames or World IDs repeated within the batch.
+fn precheck(names: &[BatchName], blocklist: &Blocklist) -> Vec<Option<String>> {
+	let mut usernames = HashSet::new();
+	let mut nullifier_hashes = HashSet::new();
+
+	names
+		.iter()
+		.map(|entry| {
+			if let Some(failure) = username_failures(
+				&entry.username,
+				&entry.verification_level.0,
+				&entry.nullifier_hash,
+				blocklist,
+			)
+			.into_iter()
+			.next()
+			{
+				return Some(failure.message);
+			}
+
+			if !usernames.insert(entry.username.to_lowercase()) {
+				return Some(USERNAME_TAKEN.to_string());
+			}
+
+			if !nullifier_hashes.insert(entry.nullifier_hash.as_str()) {
+				return Some(WORLD_ID_TAKEN.to_string());
+			}
+
+			None
+		})
+		.collect()
+}
+
+/// Register a single name, checking it against the names already stored (including earlier ones in the batch).
+async fn register_entry(
+	conn: &mut PgConnection,
+	config: &Config,
+	entry: BatchName,
+) -> Result<BatchResult, sqlx::Error> {
+	let uniqueness_check = sqlx::query!(
+		"SELECT
+			EXISTS(SELECT 1 FROM names WHERE nullifier_hash = $2) AS world_id,
+			EXISTS(SELECT 1 FROM names WHERE LOWER(username) = LOWER($1) UNION SELECT 1 FROM old_names WHERE LOWER(old_username) = LOWER($1) UNION SELECT 1 FROM deleted_names WHERE LOWER(username) = LOWER($1)) AS username",
+		&entry.username,
+		&entry.nullifier_hash
+	)
+	.fetch_one(&mut *conn)
+	.await?;
+
+	if uniqueness_check.username.unwrap_or_default() {
+		return Ok(BatchResult::failed(
+			entry.username,
+			USERNAME_TAKEN.to_string(),
+		));
+	}
+
+	if uniqueness_check.world_id.unwrap_or_default() {
+		return Ok(BatchResult::failed(
+			entry.username,
+			WORLD_ID_TAKEN.to_string(),
+		));
+	}
+
+	if address_at_limit(
+		&mut *conn,
+		&entry.address.to_checksum(None),
+		config.max_usernames_per_address,
+	)
+	.await?
+	{
+		return Ok(BatchResult::failed(
+			entry.username,
+			ADDRESS_LIMIT_REACHED.to_string(),
+		));
+	}
+
+	let name = Name::new(
+		entry.username,
+		&entry.address,
+		entry.profile_picture_url,
+		entry.nullifier_hash,
+		&entry.verification_level,
+		None,
+	);
+
+	name.insert_with(&mut *conn, &config.ens_domains).await?;
+
+	Ok(BatchResult::registered(name.username))
+}
+
+pub fn docs(op: aide::transform::TransformOperation) -> aide::transform::TransformOperation {
+	op.description("Register pre-verified usernames in a single transaction, skipping World ID proof verification. Format, blocklist, uniqueness and per-address limit rules still apply, and each name's outcome is reported individually. Batches are capped in size. Requests must be signed: `x-signature-timestamp` is the current Unix time and `x-signature` the hex-encoded HMAC-SHA256 of `{timestamp}.{body}` under the system HMAC secret.")
+		.response::<413, ErrorResponse>()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn entry(username: &str, nullifier_hash: &str) -> BatchName {
+		serde_json::from_value(serde_json::json!({
+			"username": username,
+			"address": "0x0000000000000000000000000000000000000001",
+			"profile_picture_url": null,
+			"nullifier_hash": nullifier_hash,
+			"verification_level": "orb",
+		}))
+		.unwrap()
+	}
+
+	#[test]
+	fn reports_each_name_in_a_mixed_batch() {
+		let blocklist = Blocklist::new("admin", "badword", "");
+		let names = [
+			entry("alice", "0x1"),
+			entry("admin", "0x2"),
+			entry("ALICE", "0x3"),
+			entry("bobby", "0x4"),
+		];
+
+		let failures = precheck(&names, &blocklist);
+
+		assert_eq!(failures.len(), names.len());
+		assert_eq!(failures[0], None);
+		assert!(failures[1].is_some());
+		assert_eq!(failures[2].as_deref(), Some(USERNAME_TAKEN));
+		assert_eq!(failures[3], None);
+	}
+
+	#[test]
+	fn rejects_world_ids_repeated_within_a_batch() {
+		let blocklist = Blocklist::new("admin", "badword", "");
+		let names = [entry("alice", "0x1"), entry("bobby", "0x1")];
+
+		assert_eq!(
+			precheck(&names, &blocklist),
+			[None, Some(WORLD_ID_TAKEN.to_string())]
+		);
+	}
+}
@@ -0,0 +1,54 @@
+use axum::extract::Path;
+use axum_jsonschema::Json;
+use schemars::schema_for;
+
+use crate::types::{
+	AvailabilityPayload, ENSQueryPayload, ErrorResponse, QueryAddressesPayload,
+	QueryUsernamesPayload, RegisterUsernamePayload, RenamePayload, UpdateUsernamePayload,
+};
+
+#[allow(clippy::unused_async)]
+pub async fn schema(Path(name): Path<String>) -> Result<Json<serde_json::Value>, ErrorResponse> {
+	let schema = match name.as_str() {
+		"RegisterUsernamePayload" => schema_for!(RegisterUsernamePayload),
+		"UpdateUsernamePayload" => schema_for!(UpdateUsernamePayload),
+		"RenamePayload" => schema_for!(RenamePayload),
+		"QueryAddressesPayload" => schema_for!(QueryAddressesPayload),
+		"QueryUsernamesPayload" => schema_for!(QueryUsernamesPayload),
+		"AvailabilityPayload" => schema_for!(AvailabilityPayload),
+		"ENSQueryPayload" => schema_for!(ENSQueryPayload),
+		_ => return Err(ErrorResponse::not_found(format!("No schema for {name:?}"))),
+	};
+
+	Ok(Json(serde_json::to_value(schema)?))
+}
+
+pub fn docs(op: aide::transform::TransformOperation) -> aide::transform::TransformOperation {
+	op.description("Get the JSON Schema of a request payload (e.g. `RegisterUsernamePayload`), for client-side validation.")
+		.response::<404, ErrorResponse>()
+}
+
+#[cfg(test)]
+mod tests {
+	use http::StatusCode;
+
+	use super::*;
+
+	#[tokio::test]
+	async fn describes_the_register_payload() {
+		let Json(schema) = schema(Path("RegisterUsernamePayload".to_string()))
+			.await
+			.unwrap();
+
+		let properties = schema["properties"].as_object().unwrap();
+		assert!(properties.contains_key("username"));
+		assert!(properties.contains_key("address"));
+	}
+
+	#[tokio::test]
+	async fn rejects_unknown_types() {
+		let error = schema(Path("Config".to_string())).await.unwrap_err();
+
+		assert_eq!(error.status(), StatusCode::NOT_FOUND);
+	}
+}
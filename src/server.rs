@@ -1,11 +1,22 @@
 use aide::openapi::{self, OpenApi};
 use anyhow::Result;
-use axum::Extension;
-use std::{env, net::SocketAddr};
-use tokio::{net::TcpListener, signal};
+use axum::{extract::DefaultBodyLimit, middleware, response::Response, Extension, Router};
+use http::{HeaderName, HeaderValue};
+use std::{
+	env,
+	future::{Future, IntoFuture},
+	net::SocketAddr,
+	sync::Arc,
+	time::Duration,
+};
+use tokio::{net::TcpListener, signal, sync::Notify};
 
 use crate::{config::Config, routes};
 
+/// Version of the response contract. Bump whenever a response shape changes.
+pub const API_VERSION: &str = "1";
+pub const API_VERSION_HEADER: HeaderName = HeaderName::from_static("x-api-version");
+
 pub async fn start(mut config: Config) -> Result<()> {
 	let mut openapi = OpenApi {
 		info: openapi::Info {
@@ -16,10 +27,15 @@ pub async fn start(mut config: Config) -> Result<()> {
 		..OpenApi::default()
 	};
 
-	let router = routes::handler()
+	let db = config.db_extension();
+
+	let shutdown_timeout = config.shutdown_timeout;
+	let router = routes::handler(&config)
 		.finish_api(&mut openapi)
 		.layer(Extension(openapi))
-		.layer(config.db_extension())
+		.layer(middleware::map_response(set_api_version))
+		.layer(DefaultBodyLimit::max(config.max_body_size))
+		.layer(db)
 		.layer(config.blocklist_extension())
 		.layer(config.extension());
 
@@ -31,13 +47,46 @@ pub async fn start(mut config: Config) -> Result<()> {
 
 	tracing::info!("Starting server on {addr}...");
 
-	axum::serve(listener, router.into_make_service())
-		.with_graceful_shutdown(shutdown_signal())
-		.await?;
+	serve(listener, router, shutdown_signal(), shutdown_timeout).await
+}
+
+/// Serve until `signal` resolves. Then stop accepting connections and give in-flight requests a bounded time to finish.
+async fn serve(
+	listener: TcpListener,
+	router: Router,
+	signal: impl Future<Output = ()> + Send + 'static,
+	drain_timeout: Duration,
+) -> Result<()> {
+	let shutdown = Arc::new(Notify::new());
+	let server = axum::serve(listener, router.into_make_service()).with_graceful_shutdown({
+		let shutdown = shutdown.clone();
+		async move {
+			signal.await;
+			tracing::info!("Shutting down, draining in-flight requests...");
+			shutdown.notify_one();
+		}
+	});
+
+	tokio::select! {
+		result = server.into_future() => result?,
+		() = async {
+			shutdown.notified().await;
+			tokio::time::sleep(drain_timeout).await;
+		} => tracing::warn!("Timed out draining in-flight requests"),
+	}
 
 	Ok(())
 }
 
+#[allow(clippy::unused_async)]
+async fn set_api_version(mut response: Response) -> Response {
+	response
+		.headers_mut()
+		.insert(API_VERSION_HEADER, HeaderValue::from_static(API_VERSION));
+
+	response
+}
+
 async fn shutdown_signal() {
 	let ctrl_c = async {
 		signal::ctrl_c()
@@ -61,3 +110,141 @@ async fn shutdown_signal() {
 		() = terminate => {},
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use axum::{
+		body::{Body, Bytes},
+		routing::{get, post},
+		Json,
+	};
+	use http::{header::CONTENT_TYPE, Request, StatusCode};
+	use tower::ServiceExt;
+
+	use super::*;
+
+	#[tokio::test]
+	async fn responses_carry_the_api_version() {
+		let app = Router::new()
+			.route("/", get(|| async { "ok" }))
+			.layer(middleware::map_response(set_api_version));
+
+		for uri in ["/", "/missing"] {
+			let response = app
+				.clone()
+				.oneshot(Request::get(uri).body(Body::empty()).unwrap())
+				.await
+				.unwrap();
+
+			assert_eq!(
+				response.headers().get(API_VERSION_HEADER),
+				Some(&HeaderValue::from_static(API_VERSION)),
+				"{uri}"
+			);
+			if uri == "/missing" {
+				// Errors carry it too, so clients can tell which contract they failed against.
+				assert_eq!(response.status(), StatusCode::NOT_FOUND);
+			}
+		}
+	}
+
+	/// Serve a route taking `handle_for` to answer, shutting down once `shutdown` is notified.
+	async fn slow_server(
+		handle_for: Duration,
+		drain_timeout: Duration,
+	) -> (String, Arc<Notify>, tokio::task::JoinHandle<Result<()>>) {
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let addr = listener.local_addr().unwrap();
+		let shutdown = Arc::new(Notify::new());
+
+		let app = Router::new().route(
+			"/",
+			get(move || async move {
+				tokio::time::sleep(handle_for).await;
+				"done"
+			}),
+		);
+		let signal = {
+			let shutdown = shutdown.clone();
+			async move { shutdown.notified().await }
+		};
+		let server = tokio::spawn(serve(listener, app, signal, drain_timeout));
+
+		(format!("http://{addr}/"), shutdown, server)
+	}
+
+	#[tokio::test]
+	async fn drains_in_flight_requests_on_shutdown() {
+		let (url, shutdown, server) =
+			slow_server(Duration::from_millis(300), Duration::from_secs(5)).await;
+
+		let request = tokio::spawn(async move { reqwest::get(url).await?.text().await });
+		// Let the request reach the handler before shutting down.
+		tokio::time::sleep(Duration::from_millis(100)).await;
+		shutdown.notify_one();
+
+		assert_eq!(request.await.unwrap().unwrap(), "done");
+		server.await.unwrap().unwrap();
+	}
+
+	#[tokio::test]
+	async fn stops_draining_after_the_timeout() {
+		let (url, shutdown, server) =
+			slow_server(Duration::from_secs(30), Duration::from_millis(200)).await;
+
+		let request = tokio::spawn(reqwest::get(url));
+		tokio::time::sleep(Duration::from_millis(100)).await;
+		shutdown.notify_one();
+
+		tokio::time::timeout(Duration::from_secs(5), server)
+			.await
+			.expect("server should stop once the drain timeout passes")
+			.unwrap()
+			.unwrap();
+		request.abort();
+	}
+
+	#[tokio::test]
+	async fn rejects_oversized_bodies() {
+		let max_body_size = Config::for_tests().max_body_size;
+		let app = Router::new()
+			.route(
+				"/raw",
+				post(|body: Bytes| async move { body.len().to_string() }),
+			)
+			.route(
+				"/json",
+				post(|Json(names): Json<Vec<String>>| async move { names.len().to_string() }),
+			)
+			.layer(DefaultBodyLimit::max(max_body_size));
+
+		let status = |uri: &'static str, body: String| {
+			let app = app.clone();
+			async move {
+				app.oneshot(
+					Request::post(uri)
+						.header(CONTENT_TYPE, "application/json")
+						.body(Body::from(body))
+						.unwrap(),
+				)
+				.await
+				.unwrap()
+				.status()
+			}
+		};
+		let oversized = format!("[\"{}\"]", "a".repeat(max_body_size));
+
+		assert_eq!(
+			status("/raw", oversized.clone()).await,
+			StatusCode::PAYLOAD_TOO_LARGE
+		);
+		assert_eq!(
+			status("/json", oversized).await,
+			StatusCode::PAYLOAD_TOO_LARGE
+		);
+		assert_eq!(
+			status("/json", "[\"alice\"]".to_string()).await,
+			StatusCode::OK
+		);
+	}
+}
@@ -0,0 +1,127 @@
+use axum::Extension;
+use std::{
+	collections::HashMap,
+	future::Future,
+	sync::{Arc, Mutex},
+};
+use tokio::sync::OnceCell;
+
+#[allow(clippy::module_name_repetitions)]
+pub type SingleFlightExt<T> = Extension<Arc<SingleFlight<T>>>;
+
+/// Deduplicates concurrent work by key: callers asking for a key that is already in flight await its result instead of starting their own.
+#[derive(Debug)]
+pub struct SingleFlight<T> {
+	in_flight: Mutex<HashMap<String, Arc<OnceCell<Arc<T>>>>>,
+}
+
+impl<T> Default for SingleFlight<T> {
+	fn default() -> Self {
+		Self {
+			in_flight: Mutex::default(),
+		}
+	}
+}
+
+impl<T> SingleFlight<T> {
+	/// Run `work` for `key`, unless it is already running, in which case share that run's result.
+	pub async fn run<F, Fut>(&self, key: &str, work: F) -> Arc<T>
+	where
+		F: FnOnce() -> Fut,
+		Fut: Future<Output = T>,
+	{
+		let cell = self
+			.in_flight
+			.lock()
+			.unwrap()
+			.entry(key.to_string())
+			.or_default()
+			.clone();
+
+		let value = cell
+			.get_or_init(|| async { Arc::new(work().await) })
+			.await
+			.clone();
+
+		// Results aren't cached: once a run completes, the next caller starts a fresh one.
+		{
+			let mut in_flight = self.in_flight.lock().unwrap();
+			if in_flight.get(key).is_some_and(|c| Arc::ptr_eq(c, &cell)) {
+				in_flight.remove(key);
+			}
+		}
+
+		value
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use futures::future::join_all;
+	use std::sync::atomic::{AtomicUsize, Ordering};
+	use tokio::sync::Notify;
+
+	use super::*;
+
+	#[tokio::test]
+	async fn concurrent_calls_for_a_key_run_once() {
+		let flight = SingleFlight::default();
+		let runs = AtomicUsize::new(0);
+		let release = Notify::new();
+
+		let calls = (0..10).map(|_| {
+			flight.run("alice", || async {
+				runs.fetch_add(1, Ordering::SeqCst);
+				// Hold the run open until every caller has joined it.
+				release.notified().await;
+				"record"
+			})
+		});
+
+		let (results, ()) = tokio::join!(join_all(calls), async {
+			tokio::task::yield_now().await;
+			release.notify_one();
+		});
+
+		assert_eq!(runs.load(Ordering::SeqCst), 1);
+		assert_eq!(results.len(), 10);
+		assert!(results.iter().all(|result| **result == "record"));
+	}
+
+	#[tokio::test]
+	async fn different_keys_run_separately() {
+		let flight = SingleFlight::default();
+		let runs = AtomicUsize::new(0);
+
+		let (alice, bob) = tokio::join!(
+			flight.run("alice", || async {
+				runs.fetch_add(1, Ordering::SeqCst);
+				"alice"
+			}),
+			flight.run("bob", || async {
+				runs.fetch_add(1, Ordering::SeqCst);
+				"bob"
+			}),
+		);
+
+		assert_eq!(runs.load(Ordering::SeqCst), 2);
+		assert_eq!((*alice, *bob), ("alice", "bob"));
+	}
+
+	#[tokio::test]
+	async fn results_are_not_cached() {
+		let flight = SingleFlight::default();
+		let runs = AtomicUsize::new(0);
+
+		for _ in 0..3 {
+			flight
+				.run("alice", || async {
+					runs.fetch_add(1, Ordering::SeqCst);
+				})
+				.await;
+		}
+
+		assert_eq!(runs.load(Ordering::SeqCst), 3);
+		assert!(flight.in_flight.lock().unwrap().is_empty());
+	}
+}
@@ -1,6 +1,9 @@
-use crate::types::{Address, VerificationLevel};
+use crate::{
+	types::{Address, HexBytes, VerificationLevel},
+	utils::name_nodes,
+};
 use chrono::Utc;
-use sqlx::prelude::FromRow;
+use sqlx::{prelude::FromRow, PgExecutor};
 use sqlxinsert::PgInsert;
 use url::Url;
 
@@ -21,6 +24,12 @@ pub struct Name {
 	pub created_at: chrono::NaiveDateTime,
 	/// The time at which this name was last updated.
 	pub updated_at: chrono::NaiveDateTime,
+	/// ENS content hash of the owner's decentralized website.
+	pub contenthash: Option<Vec<u8>>,
+	/// X coordinate of the owner's ENS public key.
+	pub pubkey_x: Option<Vec<u8>>,
+	/// Y coordinate of the owner's ENS public key.
+	pub pubkey_y: Option<Vec<u8>>,
 }
 
 impl Name {
@@ -30,6 +39,7 @@ impl Name {
 		profile_picture_url: Option<Url>,
 		nullifier_hash: String,
 		verification_level: &VerificationLevel,
+		contenthash: Option<HexBytes>,
 	) -> Self {
 		Self {
 			username,
@@ -39,8 +49,42 @@ impl Name {
 			address: address.to_checksum(None),
 			verification_level: verification_level.to_string(),
 			profile_picture_url: profile_picture_url.map(|u| u.to_string()),
+			contenthash: contenthash.map(|c| c.0.to_vec()),
+			pubkey_x: None,
+			pubkey_y: None,
 		}
 	}
+
+	/// Insert the name along with its node under each ENS domain, with any executor so it can be part of a larger transaction.
+	pub async fn insert_with<'e>(
+		&self,
+		executor: impl PgExecutor<'e>,
+		ens_domains: &[String],
+	) -> Result<(), sqlx::Error> {
+		sqlx::query!(
+			"WITH name AS (
+				INSERT INTO names (username, address, profile_picture_url, nullifier_hash, verification_level, created_at, updated_at, contenthash, pubkey_x, pubkey_y)
+				VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+				RETURNING username
+			)
+			INSERT INTO name_nodes (node, username) SELECT UNNEST($11::bytea[]), username FROM name",
+			self.username,
+			self.address,
+			self.profile_picture_url,
+			self.nullifier_hash,
+			self.verification_level,
+			self.created_at,
+			self.updated_at,
+			self.contenthash,
+			self.pubkey_x,
+			self.pubkey_y,
+			&name_nodes(&self.username, ens_domains)
+		)
+		.execute(executor)
+		.await?;
+
+		Ok(())
+	}
 }
 
 #[allow(dead_code)]
@@ -55,3 +99,25 @@ pub struct NameSearch {
 	pub address: String,
 	pub profile_picture_url: Option<String>,
 }
+
+/// A name close enough to a search query to be ranked into the results.
+#[derive(Debug)]
+pub struct SearchCandidate {
+	pub username: String,
+	pub address: String,
+	pub profile_picture_url: Option<String>,
+	pub verification_level: String,
+	pub updated_at: chrono::NaiveDateTime,
+	/// Trigram distance from the query, lower is closer.
+	pub distance: f32,
+}
+
+impl From<SearchCandidate> for NameSearch {
+	fn from(value: SearchCandidate) -> Self {
+		Self {
+			username: value.username,
+			address: value.address,
+			profile_picture_url: value.profile_picture_url,
+		}
+	}
+}
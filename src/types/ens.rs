@@ -18,6 +18,8 @@ sol! {
 	function addr(bytes32 node) returns (bytes memory);
 	function addr(bytes32 node, uint coinType) returns (bytes memory);
 	function text(bytes32 node, string key) returns (string);
+	function contenthash(bytes32 node) returns (bytes memory);
+	function pubkey(bytes32 node) returns (bytes32 x, bytes32 y);
 
 	struct GatewayResponse {
 		address sender;
@@ -30,8 +32,8 @@ sol! {
 pub enum Method {
 	Abi,
 	Name,
-	PubKey,
-	ContentHash,
+	PubKey(Vec<u8>),
+	ContentHash(Vec<u8>),
 	Addr(Vec<u8>),
 	AddrMultichain,
 	InterfaceImplementer,
@@ -39,6 +41,7 @@ pub enum Method {
 }
 
 impl resolveCall {
+	/// Decode the DNS-encoded name as sent, without normalizing it, since the node was computed over it.
 	pub fn parse_name(&self) -> Result<String, FromUtf8Error> {
 		Ok(decode_ens_name(&String::from_utf8(self.name.to_vec())?))
 	}
@@ -47,8 +50,14 @@ impl resolveCall {
 		let method = match hex::encode(&self.data[..4]).as_str() {
 			"2203ab56" => Method::Abi,
 			"691f3431" => Method::Name,
-			"c8690233" => Method::PubKey,
-			"bc1c58d1" => Method::ContentHash,
+			"c8690233" => {
+				let pubkey = pubkeyCall::abi_decode(&self.data, true)?;
+				Method::PubKey(pubkey.node.to_vec())
+			},
+			"bc1c58d1" => {
+				let contenthash = contenthashCall::abi_decode(&self.data, true)?;
+				Method::ContentHash(contenthash.node.to_vec())
+			},
 			"85337958" => {
 				let addr = addr_0Call::abi_decode(&self.data, true)?;
 				Method::Addr(addr.node.to_vec())
@@ -75,3 +84,35 @@ impl resolveCall {
 		Ok(method)
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use alloy::primitives::B256;
+
+	fn resolve_call(data: Vec<u8>) -> resolveCall {
+		resolveCall {
+			name: b"\x05alice\x05world\x02id\x00".to_vec().into(),
+			data: data.into(),
+		}
+	}
+
+	#[test]
+	fn decodes_contenthash_calls() {
+		let node = B256::repeat_byte(0xab);
+		let call = resolve_call(contenthashCall { node }.abi_encode());
+
+		assert_eq!(call.parse_name().unwrap(), "alice.world.id");
+		let Method::ContentHash(decoded) = call.parse_method().unwrap() else {
+			panic!("expected a contenthash call");
+		};
+		assert_eq!(decoded, node.to_vec());
+	}
+
+	#[test]
+	fn rejects_unknown_methods() {
+		assert!(resolve_call(vec![0xde, 0xad, 0xbe, 0xef])
+			.parse_method()
+			.is_err());
+	}
+}
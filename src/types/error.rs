@@ -11,18 +11,69 @@ use serde::{Deserialize, Serialize};
 pub struct ErrorResponse {
 	error: String,
 	status: StatusCode,
+	failures: Vec<ValidationFailure>,
 }
 
 #[derive(Debug, Serialize, JsonSchema)]
 struct ErrorResponseSchema {
 	error: String,
+	/// All validation failures, when the request failed validation.
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	errors: Vec<ValidationFailure>,
+}
+
+/// A single reason a request failed validation.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ValidationFailure {
+	/// Machine-readable failure code.
+	pub code: String,
+	/// Human-readable description of the failure.
+	pub message: String,
+}
+
+impl ValidationFailure {
+	pub fn new(code: &str, message: &str) -> Self {
+		Self {
+			code: code.to_string(),
+			message: message.to_string(),
+		}
+	}
 }
 
 impl ErrorResponse {
+	pub const fn status(&self) -> StatusCode {
+		self.status
+	}
+
 	pub const fn not_found(error: String) -> Self {
 		Self {
 			error,
 			status: StatusCode::NOT_FOUND,
+			failures: Vec::new(),
+		}
+	}
+
+	pub const fn gone(error: String) -> Self {
+		Self {
+			error,
+			status: StatusCode::GONE,
+			failures: Vec::new(),
+		}
+	}
+
+	pub const fn bad_request(error: String) -> Self {
+		Self {
+			error,
+			status: StatusCode::BAD_REQUEST,
+			failures: Vec::new(),
+		}
+	}
+
+	pub const fn payload_too_large(error: String) -> Self {
+		Self {
+			error,
+			status: StatusCode::PAYLOAD_TOO_LARGE,
+			failures: Vec::new(),
 		}
 	}
 
@@ -31,6 +82,7 @@ impl ErrorResponse {
 		Self {
 			error,
 			status: StatusCode::UNAUTHORIZED,
+			failures: Vec::new(),
 		}
 	}
 
@@ -39,6 +91,21 @@ impl ErrorResponse {
 		Self {
 			error,
 			status: StatusCode::UNPROCESSABLE_ENTITY,
+			failures: Vec::new(),
+		}
+	}
+
+	/// A validation error listing every failure. The first failure's message is used as the top-level error.
+	pub fn validation_failures(failures: Vec<ValidationFailure>) -> Self {
+		let error = failures
+			.first()
+			.map_or_else(|| "Validation Error".to_string(), |f| f.message.clone());
+		tracing::error!("Validation Error: {:?}", failures);
+
+		Self {
+			error,
+			status: StatusCode::UNPROCESSABLE_ENTITY,
+			failures,
 		}
 	}
 
@@ -47,6 +114,7 @@ impl ErrorResponse {
 		Self {
 			error,
 			status: StatusCode::INTERNAL_SERVER_ERROR,
+			failures: Vec::new(),
 		}
 	}
 }
@@ -62,7 +130,14 @@ impl IntoResponse for ErrorResponse {
 		if self.status != StatusCode::NOT_FOUND {
 			tracing::error!(error = %self.error, status = ?self.status);
 		}
-		(self.status, Json(ErrorResponseSchema { error: self.error })).into_response()
+		(
+			self.status,
+			Json(ErrorResponseSchema {
+				error: self.error,
+				errors: self.failures,
+			}),
+		)
+			.into_response()
 	}
 }
 
@@ -77,23 +152,75 @@ impl OperationOutput for ErrorResponse {
 	}
 }
 
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ENSErrorKind {
+	/// The request itself is invalid, retrying it won't help.
+	#[default]
+	Client,
+	/// The gateway failed to answer a valid request, retrying may succeed.
+	Transient,
+}
+
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct ENSErrorResponse {
 	/// A human-readable error message.
 	pub message: String,
+	/// Machine-readable error code, e.g. `invalid_node`, `record_not_found` or `unsupported_method`.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub code: Option<String>,
+	/// Whether the error was caused by the request or by the gateway. Only present when enabled on the server.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub kind: Option<ENSErrorKind>,
+	/// Whether retrying the same request may succeed. Only present when enabled on the server.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub retryable: Option<bool>,
+	#[serde(skip)]
+	#[schemars(skip)]
+	classification: ENSErrorKind,
 }
 
 impl ENSErrorResponse {
-	pub fn new(message: &str) -> Self {
+	pub fn new(code: &str, message: &str) -> Self {
 		Self {
 			message: message.to_string(),
+			code: Some(code.to_string()),
+			kind: None,
+			retryable: None,
+			classification: ENSErrorKind::Client,
+		}
+	}
+
+	pub fn transient(code: &str, message: &str) -> Self {
+		Self {
+			classification: ENSErrorKind::Transient,
+			..Self::new(code, message)
+		}
+	}
+
+	/// Client errors are a bad request, transient ones mean the gateway is unavailable for now.
+	pub const fn status(&self) -> StatusCode {
+		match self.classification {
+			ENSErrorKind::Client => StatusCode::BAD_REQUEST,
+			ENSErrorKind::Transient => StatusCode::SERVICE_UNAVAILABLE,
+		}
+	}
+
+	/// Expose the error classification and retry hint in the response body, if enabled.
+	#[must_use]
+	pub fn classified(mut self, enabled: bool) -> Self {
+		if enabled {
+			self.kind = Some(self.classification);
+			self.retryable = Some(self.classification == ENSErrorKind::Transient);
 		}
+
+		self
 	}
 }
 
 impl IntoResponse for ENSErrorResponse {
 	fn into_response(self) -> axum::response::Response {
-		(StatusCode::BAD_REQUEST, Json(self)).into_response()
+		(self.status(), Json(self)).into_response()
 	}
 }
 
@@ -112,7 +239,52 @@ impl OperationOutput for ENSErrorResponse {
 		operation: &mut Operation,
 	) -> Vec<(Option<u16>, aide::openapi::Response)> {
 		Self::operation_response(ctx, operation).map_or_else(Vec::new, |res| {
-			vec![(Some(StatusCode::BAD_REQUEST.as_u16()), res)]
+			vec![
+				(Some(StatusCode::BAD_REQUEST.as_u16()), res.clone()),
+				(Some(StatusCode::SERVICE_UNAVAILABLE.as_u16()), res),
+			]
 		})
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn decode_failures_are_client_errors() {
+		let error =
+			ENSErrorResponse::new("decode_error", "Failed to decode payload.").classified(true);
+
+		assert_eq!(error.kind, Some(ENSErrorKind::Client));
+		assert_eq!(error.retryable, Some(false));
+		assert_eq!(error.into_response().status(), StatusCode::BAD_REQUEST);
+	}
+
+	#[test]
+	fn signing_failures_are_transient() {
+		let error = ENSErrorResponse::transient("signing_failed", "Failed to sign response.")
+			.classified(true);
+
+		assert_eq!(error.kind, Some(ENSErrorKind::Transient));
+		assert_eq!(error.retryable, Some(true));
+		assert_eq!(
+			error.into_response().status(),
+			StatusCode::SERVICE_UNAVAILABLE
+		);
+	}
+
+	#[test]
+	fn classification_is_hidden_unless_enabled() {
+		let error = ENSErrorResponse::transient("signing_failed", "Failed to sign response.")
+			.classified(false);
+
+		assert_eq!(error.kind, None);
+		assert_eq!(error.retryable, None);
+		// The status code reflects the classification either way.
+		assert_eq!(
+			error.into_response().status(),
+			StatusCode::SERVICE_UNAVAILABLE
+		);
+	}
+}
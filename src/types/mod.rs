@@ -5,12 +5,15 @@ mod request;
 mod response;
 mod wrappers;
 
-pub use database::{MovedRecord, Name, NameSearch};
+pub use database::{MovedRecord, Name, NameSearch, SearchCandidate};
 pub use ens::{resolveCall as ResolveRequest, Method};
-pub use error::{ENSErrorResponse, ErrorResponse};
+pub use error::{ENSErrorResponse, ErrorResponse, ValidationFailure};
 pub use request::{
-	ENSQueryPayload, QueryAddressesPayload, RegisterUsernamePayload, RenamePayload,
-	UpdateUsernamePayload,
+	AvailabilityPayload, ENSQueryPayload, QueryAddressesPayload, QueryUsernamesPayload,
+	RegisterUsernamePayload, RenamePayload, SearchParams, SearchSort, UpdateUsernamePayload,
 };
-pub use response::{ENSResponse, UsernameRecord};
-pub use wrappers::{Address, VerificationLevel};
+pub use response::{
+	AddressExistsResponse, ENSResponse, ResolvedUsernameRecord, UsernameAvailability,
+	UsernameRecord,
+};
+pub use wrappers::{Address, HexBytes, VerificationLevel};
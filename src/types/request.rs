@@ -3,7 +3,7 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use url::Url;
 
-use super::{Address, VerificationLevel};
+use super::{Address, HexBytes, VerificationLevel};
 
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct RegisterUsernamePayload {
@@ -17,6 +17,8 @@ pub struct RegisterUsernamePayload {
 	pub address: Address,
 	/// The user's profile picture URL.
 	pub profile_picture_url: Option<Url>,
+	/// 0x-prefixed ENS content hash for the user's decentralized website.
+	pub contenthash: Option<HexBytes>,
 	/// 0x-prefixed hex string of the World ID nullifier hash.
 	pub nullifier_hash: String,
 	/// World ID verification level the user holds.
@@ -39,6 +41,23 @@ impl RegisterUsernamePayload {
 pub struct QueryAddressesPayload {
 	/// A list of addresses to resolve.
 	pub addresses: Vec<Address>,
+	/// Whether to include when each record was last updated.
+	#[serde(default)]
+	pub include_timestamps: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct QueryUsernamesPayload {
+	/// A list of usernames to resolve.
+	pub usernames: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct AvailabilityPayload {
+	/// A list of usernames to check.
+	pub usernames: Vec<String>,
+	/// World ID verification level the usernames would be registered with. If not provided, usernames with a device suffix (e.g. `alice.1234`) are checked against the device rules and the rest against the orb rules.
+	pub verification_level: Option<VerificationLevel>,
 }
 
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
@@ -51,6 +70,20 @@ pub struct UpdateUsernamePayload {
 	pub address: Address,
 	/// The username's new profile picture URL. If not provided, the exixting profile picture URL will be deleted.
 	pub profile_picture_url: Option<Url>,
+	/// The username's new ENS content hash. Leaves the existing content hash unchanged if not provided, or deletes it if `null`.
+	#[serde(
+		default,
+		deserialize_with = "present",
+		skip_serializing_if = "Option::is_none"
+	)]
+	pub contenthash: Option<Option<HexBytes>>,
+	/// The username's new ENS public key. Leaves the existing public key unchanged if not provided, or deletes it if `null`.
+	#[serde(
+		default,
+		deserialize_with = "present",
+		skip_serializing_if = "Option::is_none"
+	)]
+	pub pubkey: Option<Option<PubKey>>,
 	/// 0x-prefixed hex string of the World ID nullifier hash.
 	pub nullifier_hash: String,
 	/// World ID verification level the user holds.
@@ -69,6 +102,29 @@ impl UpdateUsernamePayload {
 	}
 }
 
+/// Deserialize a field that is present (even as `null`) to `Some`, so a missing field can be told apart from an explicit `null`.
+fn present<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+	T: Deserialize<'de>,
+	D: serde::Deserializer<'de>,
+{
+	T::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct PubKey {
+	/// 0x-prefixed, 32-byte X coordinate of the public key.
+	pub x: HexBytes,
+	/// 0x-prefixed, 32-byte Y coordinate of the public key.
+	pub y: HexBytes,
+}
+
+impl PubKey {
+	pub fn is_valid(&self) -> bool {
+		self.x.len() == 32 && self.y.len() == 32
+	}
+}
+
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct RenamePayload {
 	/// 0x-prefixed hex string of the World ID proof.
@@ -97,8 +153,113 @@ impl RenamePayload {
 	}
 }
 
+/// How to order search results.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchSort {
+	/// Closest matches first.
+	#[default]
+	Relevance,
+	/// Orb-verified users first, then closest matches.
+	Verification,
+	/// Most recently updated names first.
+	Recent,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct SearchParams {
+	/// How to order the results. Defaults to relevance.
+	#[serde(default)]
+	pub sort: SearchSort,
+}
+
 #[derive(Debug, Deserialize, JsonSchema)]
 pub struct ENSQueryPayload {
 	pub data: String,
 	pub sender: Address,
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn update_payload(fields: &serde_json::Value) -> UpdateUsernamePayload {
+		let mut payload = serde_json::json!({
+			"proof": "0x00",
+			"merkle_root": "0x00",
+			"address": "0x0000000000000000000000000000000000000001",
+			"nullifier_hash": "0x00",
+			"verification_level": "orb",
+		});
+		payload
+			.as_object_mut()
+			.unwrap()
+			.extend(fields.as_object().unwrap().clone());
+
+		serde_json::from_value(payload).unwrap()
+	}
+
+	#[test]
+	fn missing_contenthash_leaves_it_unchanged() {
+		assert!(update_payload(&serde_json::json!({})).contenthash.is_none());
+	}
+
+	#[test]
+	fn null_contenthash_deletes_it() {
+		let payload = update_payload(&serde_json::json!({ "contenthash": null }));
+
+		assert!(matches!(payload.contenthash, Some(None)));
+	}
+
+	#[test]
+	fn provided_contenthash_replaces_it() {
+		let payload = update_payload(&serde_json::json!({ "contenthash": "0xe301" }));
+
+		assert_eq!(
+			payload.contenthash.unwrap().unwrap().to_vec(),
+			vec![0xe3, 0x01]
+		);
+	}
+
+	#[test]
+	fn missing_pubkey_leaves_it_unchanged() {
+		assert!(update_payload(&serde_json::json!({})).pubkey.is_none());
+	}
+
+	#[test]
+	fn null_pubkey_deletes_it() {
+		let payload = update_payload(&serde_json::json!({ "pubkey": null }));
+
+		assert!(matches!(payload.pubkey, Some(None)));
+	}
+
+	#[test]
+	fn provided_pubkey_replaces_it() {
+		let coordinate = format!("0x{}", "11".repeat(32));
+		let payload =
+			update_payload(&serde_json::json!({ "pubkey": { "x": coordinate, "y": coordinate } }));
+
+		assert!(payload.pubkey.unwrap().unwrap().is_valid());
+	}
+
+	#[test]
+	fn search_sort_defaults_to_relevance() {
+		let params: SearchParams = serde_json::from_str("{}").unwrap();
+
+		assert!(matches!(params.sort, SearchSort::Relevance));
+	}
+
+	#[test]
+	fn parses_search_sort_keys() {
+		for (sort, key) in [
+			(SearchSort::Relevance, "relevance"),
+			(SearchSort::Verification, "verification"),
+			(SearchSort::Recent, "recent"),
+		] {
+			let params: SearchParams =
+				serde_json::from_value(serde_json::json!({ "sort": key })).unwrap();
+
+			assert_eq!(params.sort, sort);
+		}
+	}
+}
@@ -5,7 +5,7 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use url::Url;
 
-use super::{Address, Name, NameSearch};
+use super::{Address, Name, NameSearch, ValidationFailure};
 
 #[derive(Debug, Serialize, Deserialize, JsonSchema, OperationIo)]
 pub struct ENSResponse {
@@ -13,6 +13,21 @@ pub struct ENSResponse {
 	pub data: String,
 }
 
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct AddressExistsResponse {
+	/// Whether the address has registered a World App username.
+	pub exists: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct UsernameAvailability {
+	/// Whether the username can currently be registered.
+	pub available: bool,
+	/// Why the username can't be registered, if it can't.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub reason: Option<ValidationFailure>,
+}
+
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct UsernameRecord {
 	/// The user's World App username.
@@ -21,6 +36,17 @@ pub struct UsernameRecord {
 	pub address: Address,
 	/// URL to the user's profile picture.
 	pub profile_picture_url: Option<Url>,
+	/// When the record was last updated. Only present when requested.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub updated_at: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ResolvedUsernameRecord {
+	#[serde(flatten)]
+	pub record: UsernameRecord,
+	/// The username that was requested, if it has since been renamed.
+	pub redirected_from: Option<String>,
 }
 
 #[allow(clippy::fallible_impl_from)]
@@ -30,17 +56,48 @@ impl From<Name> for UsernameRecord {
 			username: value.username,
 			address: Address(value.address.parse().unwrap()),
 			profile_picture_url: value.profile_picture_url.map(|url| url.parse().unwrap()),
+			updated_at: None,
 		}
 	}
 }
 
-#[allow(clippy::fallible_impl_from)]
-impl From<NameSearch> for UsernameRecord {
-	fn from(value: NameSearch) -> Self {
-		Self {
+/// A stored name that can't be turned into a [`UsernameRecord`].
+#[derive(Debug, thiserror::Error)]
+pub enum InvalidRecord {
+	#[error("{username:?} has an invalid address {address:?}")]
+	Address { username: String, address: String },
+	#[error("{username:?} has an invalid profile picture URL {url:?}")]
+	ProfilePictureUrl { username: String, url: String },
+}
+
+impl TryFrom<NameSearch> for UsernameRecord {
+	type Error = InvalidRecord;
+
+	fn try_from(value: NameSearch) -> Result<Self, Self::Error> {
+		let Ok(address) = value.address.parse() else {
+			return Err(InvalidRecord::Address {
+				username: value.username,
+				address: value.address,
+			});
+		};
+
+		let Ok(profile_picture_url) = value
+			.profile_picture_url
+			.as_deref()
+			.map(Url::parse)
+			.transpose()
+		else {
+			return Err(InvalidRecord::ProfilePictureUrl {
+				username: value.username,
+				url: value.profile_picture_url.unwrap_or_default(),
+			});
+		};
+
+		Ok(Self {
 			username: value.username,
-			address: Address(value.address.parse().unwrap()),
-			profile_picture_url: value.profile_picture_url.map(|url| url.parse().unwrap()),
-		}
+			address: Address(address),
+			profile_picture_url,
+			updated_at: None,
+		})
 	}
 }
@@ -35,6 +35,44 @@ impl JsonSchema for Address {
 	}
 }
 
+/// 0x-prefixed hex string representing arbitrary bytes.
+#[repr(transparent)]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HexBytes(pub alloy::primitives::Bytes);
+
+impl Deref for HexBytes {
+	type Target = alloy::primitives::Bytes;
+
+	fn deref(&self) -> &Self::Target {
+		&self.0
+	}
+}
+
+impl Display for HexBytes {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> std::fmt::Result {
+		self.0.fmt(f)
+	}
+}
+
+impl JsonSchema for HexBytes {
+	fn schema_name() -> String {
+		"HexBytes".to_string()
+	}
+
+	fn json_schema(_: &mut schemars::gen::SchemaGenerator) -> schema::Schema {
+		schema::Schema::Object(schema::SchemaObject {
+			string: Some(Box::new(schema::StringValidation {
+				pattern: Some("^0x([a-fA-F0-9]{2})*$".to_string()),
+				..Default::default()
+			})),
+			instance_type: Some(schema::SingleOrVec::Single(Box::new(
+				schema::InstanceType::String,
+			))),
+			..Default::default()
+		})
+	}
+}
+
 /// World ID verification level
 #[repr(transparent)]
 #[derive(Debug, Serialize, Deserialize)]
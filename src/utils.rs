@@ -1,4 +1,12 @@
 use alloy::primitives::keccak256;
+use subtle::ConstantTimeEq;
+use unicode_normalization::UnicodeNormalization;
+
+/// Compare two secrets in constant time, so the comparison doesn't leak how much of them matched.
+/// Use this for every security-sensitive equality check (nullifier hashes, API keys, etc.).
+pub fn secure_eq(a: &str, b: &str) -> bool {
+	a.as_bytes().ct_eq(b.as_bytes()).into()
+}
 
 pub fn namehash(name: &str) -> [u8; 32] {
 	if name.is_empty() {
@@ -14,6 +22,14 @@ pub fn namehash(name: &str) -> [u8; 32] {
 	})
 }
 
+/// ENS namehashes of a username under each of the given domains.
+pub fn name_nodes(username: &str, ens_domains: &[String]) -> Vec<Vec<u8>> {
+	ens_domains
+		.iter()
+		.map(|domain| namehash(&format!("{username}.{domain}")).to_vec())
+		.collect()
+}
+
 pub fn decode_ens_name(name: &str) -> String {
 	let mut labels: Vec<&str> = Vec::new();
 	let mut idx = 0;
@@ -28,3 +44,81 @@ pub fn decode_ens_name(name: &str) -> String {
 
 	labels.join(".")
 }
+
+/// Normalize a name as wallets may send it: trim surrounding whitespace,
+/// drop zero-width characters and variation selectors, and compose to NFC.
+pub fn normalize_name(name: &str) -> String {
+	name.trim()
+		.chars()
+		.filter(|c| {
+			!matches!(
+				c,
+				'\u{200b}' | '\u{200c}' | '\u{200d}' | '\u{2060}' | '\u{feff}' | '\u{fe0f}'
+			)
+		})
+		.nfc()
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn compares_secrets() {
+		assert!(secure_eq("0xabc123", "0xabc123"));
+		assert!(secure_eq("", ""));
+		assert!(!secure_eq("0xabc123", "0xabc124"));
+		assert!(!secure_eq("0xabc123", "0xABC123"));
+		// Inputs of different lengths never match, even when one is a prefix of the other.
+		assert!(!secure_eq("0xabc", "0xabc123"));
+		assert!(!secure_eq("0xabc123", ""));
+	}
+
+	#[test]
+	fn computes_namehash() {
+		assert_eq!(namehash(""), [0; 32]);
+		assert_eq!(
+			hex::encode(namehash("eth")),
+			"93cdeb708b7545dc668eb9280176169d1c33cfd8ed6f04690a0bcc88a93fc4ae"
+		);
+		assert_eq!(
+			hex::encode(namehash("foo.eth")),
+			"de9b09fd7c5f901e23a3f19fecc54828e9c848539801e86591bd9801b019f84f"
+		);
+	}
+
+	#[test]
+	fn computes_a_node_per_domain() {
+		let domains = ["eth".to_string(), "foo.eth".to_string()];
+		let nodes = name_nodes("foo", &domains);
+
+		assert_eq!(nodes.len(), 2);
+		assert_eq!(nodes[0], namehash("foo.eth").to_vec());
+		assert_eq!(nodes[1], namehash("foo.foo.eth").to_vec());
+	}
+
+	#[test]
+	fn decodes_dns_encoded_names() {
+		assert_eq!(
+			decode_ens_name("\u{5}alice\u{5}world\u{2}id\u{0}"),
+			"alice.world.id"
+		);
+		assert_eq!(
+			decode_ens_name("\u{8}ali\u{200b}ce\u{5}world\u{2}id\u{0}"),
+			"ali\u{200b}ce.world.id"
+		);
+	}
+
+	#[test]
+	fn normalizes_names() {
+		assert_eq!(normalize_name("  alice.world.id\n"), "alice.world.id");
+		assert_eq!(
+			normalize_name("al\u{200d}ice\u{feff}.world.id"),
+			"alice.world.id"
+		);
+		// Decomposed "é" composes to its single code point.
+		assert_eq!(normalize_name("caf\u{65}\u{301}"), "caf\u{e9}");
+		assert_eq!(normalize_name("alice.world.id"), "alice.world.id");
+	}
+}
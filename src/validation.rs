@@ -0,0 +1,320 @@
+use idkit::session::VerificationLevel;
+use regex::Regex;
+use sqlx::PgConnection;
+
+use crate::{
+	blocklist::Blocklist,
+	config::{DEVICE_USERNAME_REGEX, USERNAME_CHARACTERS_REGEX, USERNAME_REGEX},
+	types::{ErrorResponse, ValidationFailure},
+	verify,
+};
+
+// Mirrors the username regexes, which take a leading letter, 2 to 13 word characters and a trailing letter or digit.
+const MIN_USERNAME_LENGTH: usize = 4;
+const MAX_USERNAME_LENGTH: usize = 15;
+
+/// The response for a proof that failed to verify. A rejected proof is the client's fault, even when the Developer
+/// Portal's rejection isn't in the shape we expect; anything else means we couldn't verify it.
+pub fn verification_error(context: &str, error: verify::Error) -> ErrorResponse {
+	match error {
+		verify::Error::Verification(e) => {
+			tracing::error!("{context} Verification Error: {}", e.detail);
+			ErrorResponse::validation_error(e.detail)
+		},
+		verify::Error::UnexpectedRejection(body) => {
+			tracing::error!("{context} Verification Error: unexpected response body {body:?}");
+			ErrorResponse::validation_error("World ID proof was rejected".to_string())
+		},
+		e => {
+			tracing::error!("{context} Server Error: {e}");
+			ErrorResponse::server_error("Failed to verify World ID proof".to_string())
+		},
+	}
+}
+
+/// Collect every reason a username can't be registered by the given World ID.
+pub fn username_failures(
+	username: &str,
+	verification_level: &VerificationLevel,
+	nullifier_hash: &str,
+	blocklist: &Blocklist,
+) -> Vec<ValidationFailure> {
+	let mut failures = Vec::new();
+
+	let (username_regex, base): (&Regex, &str) = match verification_level {
+		VerificationLevel::Orb => (&USERNAME_REGEX, username),
+		// Device usernames carry a `.1234` suffix, which isn't counted towards the length.
+		VerificationLevel::Device => (
+			&DEVICE_USERNAME_REGEX,
+			username.rsplit_once('.').map_or(username, |(base, _)| base),
+		),
+	};
+
+	let length = base.chars().count();
+	if length < MIN_USERNAME_LENGTH {
+		failures.push(ValidationFailure::new(
+			"too_short",
+			&format!("Username must be at least {MIN_USERNAME_LENGTH} characters long"),
+		));
+	} else if length > MAX_USERNAME_LENGTH {
+		failures.push(ValidationFailure::new(
+			"too_long",
+			&format!("Username must be at most {MAX_USERNAME_LENGTH} characters long"),
+		));
+	}
+
+	if !USERNAME_CHARACTERS_REGEX.is_match(base) {
+		failures.push(ValidationFailure::new(
+			"invalid_characters",
+			"Username can only contain letters, numbers and underscores",
+		));
+	}
+
+	// The regex already rejects these (they start with a digit), but say why rather than report a generic format error.
+	// Full addresses are always too long, so the prefix is all that needs checking.
+	if base.starts_with("0x") {
+		failures.push(ValidationFailure::new(
+			"address_like",
+			"Username can't look like a wallet address",
+		));
+	}
+
+	// The regex also enforces placement rules (e.g. starting with a letter), so keep it as a final guard.
+	if failures.is_empty() && !username_regex.is_match(username) {
+		failures.push(ValidationFailure::new(
+			"invalid_format",
+			"Username does not match the required pattern",
+		));
+	}
+
+	failures.extend(
+		blocklist
+			.violations(username, nullifier_hash)
+			.into_iter()
+			.map(|e| ValidationFailure::new(e.code(), &e.to_string())),
+	);
+
+	failures
+}
+
+/// Ensure a username can be registered, reporting all failures at once.
+pub fn ensure_valid_username(
+	username: &str,
+	verification_level: &VerificationLevel,
+	nullifier_hash: &str,
+	blocklist: &Blocklist,
+) -> Result<(), ErrorResponse> {
+	let failures = username_failures(username, verification_level, nullifier_hash, blocklist);
+
+	if failures.is_empty() {
+		Ok(())
+	} else {
+		Err(ErrorResponse::validation_failures(failures))
+	}
+}
+
+pub const ADDRESS_LIMIT_REACHED: &str = "This address has reached the maximum number of usernames.";
+
+/// Ensure an address can take on another username, when a per-address limit is configured.
+/// Run it in the transaction that then stores the name, see [`address_at_limit`].
+pub async fn ensure_address_below_limit(
+	conn: &mut PgConnection,
+	address: &str,
+	limit: Option<i64>,
+) -> Result<(), ErrorResponse> {
+	if address_at_limit(conn, address, limit).await? {
+		return Err(ErrorResponse::validation_error(
+			ADDRESS_LIMIT_REACHED.to_string(),
+		));
+	}
+
+	Ok(())
+}
+
+/// Whether an address already holds as many usernames as the configured per-address limit allows.
+/// The address stays locked until the surrounding transaction ends, so concurrent registrations for it can't both
+/// pass the check, and names inserted earlier in the transaction are counted.
+pub async fn address_at_limit(
+	conn: &mut PgConnection,
+	address: &str,
+	limit: Option<i64>,
+) -> Result<bool, sqlx::Error> {
+	if limit.is_none() {
+		return Ok(false);
+	}
+
+	// An address without names has no rows to lock, so registrations serialize on an advisory lock instead.
+	sqlx::query!("SELECT pg_advisory_xact_lock(hashtext($1))", address)
+		.execute(&mut *conn)
+		.await?;
+
+	let usernames = sqlx::query_scalar!(
+		r#"SELECT COUNT(*) AS "count!" FROM names WHERE address = $1"#,
+		address
+	)
+	.fetch_one(&mut *conn)
+	.await?;
+
+	Ok(limit_reached(usernames, limit))
+}
+
+const fn limit_reached(usernames: i64, limit: Option<i64>) -> bool {
+	match limit {
+		Some(limit) => usernames >= limit,
+		None => false,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use http::StatusCode;
+
+	use super::*;
+
+	fn failure_codes(username: &str, verification_level: &VerificationLevel) -> Vec<String> {
+		let blocklist = Blocklist::new("admin", "badword", "");
+
+		username_failures(username, verification_level, "0x1", &blocklist)
+			.into_iter()
+			.map(|failure| failure.code)
+			.collect()
+	}
+
+	#[test]
+	fn accepts_valid_usernames() {
+		assert!(failure_codes("alice", &VerificationLevel::Orb).is_empty());
+		assert!(failure_codes("alice_99", &VerificationLevel::Orb).is_empty());
+		assert!(failure_codes("alice.1234", &VerificationLevel::Device).is_empty());
+	}
+
+	#[test]
+	fn accepts_every_character_the_regex_does() {
+		// `\w` is Unicode-aware, so uppercase and non-ASCII letters are allowed past the first character.
+		assert!(failure_codes("aLice", &VerificationLevel::Orb).is_empty());
+		assert!(failure_codes("fran\u{e7}ois", &VerificationLevel::Orb).is_empty());
+	}
+
+	#[test]
+	fn rejects_short_usernames() {
+		assert_eq!(failure_codes("abc", &VerificationLevel::Orb), ["too_short"]);
+		assert_eq!(
+			failure_codes("abc.1234", &VerificationLevel::Device),
+			["too_short"]
+		);
+		assert!(failure_codes("abcd", &VerificationLevel::Orb).is_empty());
+	}
+
+	#[test]
+	fn rejects_long_usernames() {
+		assert_eq!(
+			failure_codes("abcdefghijklmnop", &VerificationLevel::Orb),
+			["too_long"]
+		);
+		assert!(failure_codes("abcdefghijklmno", &VerificationLevel::Orb).is_empty());
+	}
+
+	#[test]
+	fn rejects_invalid_characters() {
+		assert_eq!(
+			failure_codes("ali-ce", &VerificationLevel::Orb),
+			["invalid_characters"]
+		);
+		assert_eq!(
+			failure_codes("ali ce", &VerificationLevel::Orb),
+			["invalid_characters"]
+		);
+	}
+
+	#[test]
+	fn falls_back_to_the_regex_for_placement_rules() {
+		assert_eq!(
+			failure_codes("_alice", &VerificationLevel::Orb),
+			["invalid_format"]
+		);
+		assert_eq!(
+			failure_codes("alice_", &VerificationLevel::Orb),
+			["invalid_format"]
+		);
+		assert_eq!(
+			failure_codes("alice", &VerificationLevel::Device),
+			["invalid_format"]
+		);
+	}
+
+	#[test]
+	fn rejects_address_like_usernames() {
+		assert_eq!(
+			failure_codes("0xabc123", &VerificationLevel::Orb),
+			["address_like"]
+		);
+		assert_eq!(
+			failure_codes("0xabc.1234", &VerificationLevel::Device),
+			["address_like"]
+		);
+		assert!(failure_codes("oxabc123", &VerificationLevel::Orb).is_empty());
+	}
+
+	#[test]
+	fn reports_blocklist_violations() {
+		assert_eq!(
+			failure_codes("admin", &VerificationLevel::Orb),
+			["reserved"]
+		);
+		assert_eq!(
+			failure_codes("mybadword", &VerificationLevel::Orb),
+			["blocked_substring"]
+		);
+	}
+
+	#[test]
+	fn rejected_proofs_are_client_errors() {
+		let rejection = verify::Error::Verification(verify::ErrorResponse {
+			code: "invalid_proof".to_string(),
+			detail: "The provided proof is invalid.".to_string(),
+			attribute: None,
+		});
+		let unexpected = verify::Error::UnexpectedRejection("<html>Bad Request</html>".to_string());
+
+		for error in [rejection, unexpected] {
+			assert_eq!(
+				verification_error("Register", error).status(),
+				StatusCode::UNPROCESSABLE_ENTITY
+			);
+		}
+	}
+
+	#[test]
+	fn verification_failures_are_server_errors() {
+		let error = verify::Error::Timeout(Duration::from_secs(10));
+
+		assert_eq!(
+			verification_error("Register", error).status(),
+			StatusCode::INTERNAL_SERVER_ERROR
+		);
+	}
+
+	#[test]
+	fn enforces_the_address_limit() {
+		assert!(!limit_reached(0, Some(1)));
+		assert!(limit_reached(1, Some(1)));
+		assert!(!limit_reached(2, Some(3)));
+		assert!(limit_reached(3, Some(3)));
+		assert!(limit_reached(4, Some(3)));
+	}
+
+	#[test]
+	fn addresses_are_unlimited_by_default() {
+		assert!(!limit_reached(0, None));
+		assert!(!limit_reached(i64::MAX, None));
+	}
+
+	#[test]
+	fn reports_every_failure_at_once() {
+		assert_eq!(
+			failure_codes("ab-", &VerificationLevel::Orb),
+			["too_short", "invalid_characters"]
+		);
+	}
+}
@@ -1,5 +1,6 @@
 use reqwest::{header, StatusCode};
 use serde::Serialize;
+use std::time::Duration;
 
 use idkit::{hashing::hash_to_field, session::VerificationLevel, Proof};
 
@@ -13,6 +14,10 @@ pub enum Error {
 	Serde(#[from] serde_json::Error),
 	#[error("unexpected response")]
 	InvalidResponse(reqwest::Response),
+	#[error("verification rejected with an unexpected body: {0}")]
+	UnexpectedRejection(String),
+	#[error("verification timed out after {0:?}")]
+	Timeout(Duration),
 }
 
 #[derive(Debug, serde::Deserialize)]
@@ -34,43 +39,308 @@ struct VerificationRequest {
 	signal_hash: Option<String>,
 }
 
-/// Verify a World ID proof using the Developer Portal API.
+/// How verification requests to the Developer Portal are bounded and retried.
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+	/// Deadline for the whole verification, across every attempt and retry delay.
+	pub timeout: Duration,
+	/// How many times a transient failure (a network error or a 5xx) is retried.
+	pub max_retries: u32,
+	/// Delay before the first retry, doubled for every following one.
+	pub min_delay: Duration,
+	/// Upper bound for the delay between retries.
+	pub max_delay: Duration,
+}
+
+impl Options {
+	fn retry_delay(&self, retry: u32) -> Duration {
+		self.min_delay
+			.saturating_mul(2u32.saturating_pow(retry))
+			.min(self.max_delay)
+	}
+}
+
+impl Error {
+	/// Whether the Developer Portal might answer differently if asked again.
+	fn is_transient(&self) -> bool {
+		match self {
+			Self::Reqwest(_) => true,
+			Self::InvalidResponse(response) => response.status().is_server_error(),
+			_ => false,
+		}
+	}
+}
+
+/// Verify a World ID proof using the Developer Portal API, retrying transient failures.
 ///
 /// # Errors
 ///
-/// Errors if the proof is invalid (`Error::Verification`), or if there's an error validating the proof.
+/// Errors if the proof is invalid (`Error::Verification`), if the Developer Portal doesn't answer within `options.timeout` including retries (`Error::Timeout`), or if there's an error validating the proof.
 pub async fn dev_portal_verify_proof<V: alloy::sol_types::SolValue + Send>(
 	proof: Proof,
 	app_id: String,
 	action: &str,
 	signal: V,
 	developer_portal_url: String,
+	verify_path: &str,
+	options: Options,
 ) -> Result<(), Error> {
 	let signal = signal.abi_encode_packed();
 
-	let response = reqwest::Client::new()
-		.post(format!("{developer_portal_url}/api/v2/verify/{app_id}"))
+	let client = reqwest::Client::new();
+	let url = format!("{developer_portal_url}/{verify_path}/{app_id}");
+	let request = VerificationRequest {
+		proof: proof.proof,
+		signal_hash: if signal.is_empty() {
+			None
+		} else {
+			Some(format!("0x{:x}", hash_to_field(&signal)))
+		},
+		action: action.to_string(),
+		merkle_root: proof.merkle_root,
+		nullifier_hash: proof.nullifier_hash,
+		verification_level: proof.verification_level,
+	};
+
+	// The deadline covers reading the response body and sleeping between retries, not just sending.
+	let attempts = async {
+		let mut retry = 0;
+
+		loop {
+			match verify_once(&client, &url, &request).await {
+				Err(e) if retry < options.max_retries && e.is_transient() => {
+					tracing::warn!(retry, error = %e, "Retrying proof verification");
+					tokio::time::sleep(options.retry_delay(retry)).await;
+					retry += 1;
+				},
+				result => return result,
+			}
+		}
+	};
+
+	tokio::time::timeout(options.timeout, attempts)
+		.await
+		.map_err(|_| Error::Timeout(options.timeout))?
+}
+
+async fn verify_once(
+	client: &reqwest::Client,
+	url: &str,
+	request: &VerificationRequest,
+) -> Result<(), Error> {
+	let response = client
+		.post(url)
 		.header(header::USER_AGENT, "idkit-rs")
-		.json(&VerificationRequest {
-			proof: proof.proof,
-			signal_hash: if signal.is_empty() {
-				None
-			} else {
-				Some(format!("0x{:x}", hash_to_field(&signal)))
-			},
-			action: action.to_string(),
-			merkle_root: proof.merkle_root,
-			nullifier_hash: proof.nullifier_hash,
-			verification_level: proof.verification_level,
-		})
+		.json(request)
 		.send()
 		.await?;
 
 	match response.status() {
 		StatusCode::OK => Ok(()),
 		StatusCode::BAD_REQUEST => {
-			Err(Error::Verification(response.json::<ErrorResponse>().await?))
+			let body = response.text().await?;
+
+			// Still a rejection of the proof, even if the body isn't in the shape we expect.
+			Err(serde_json::from_str::<ErrorResponse>(&body)
+				.map_or(Error::UnexpectedRejection(body), Error::Verification))
 		},
 		_ => Err(Error::InvalidResponse(response)),
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::{
+		sync::{
+			atomic::{AtomicUsize, Ordering},
+			Arc,
+		},
+		time::Instant,
+	};
+
+	fn proof() -> Proof {
+		Proof {
+			proof: "0x00".to_string(),
+			merkle_root: "0x00".to_string(),
+			nullifier_hash: "0x00".to_string(),
+			verification_level: VerificationLevel::Orb,
+		}
+	}
+
+	const fn options(timeout: Duration, max_retries: u32) -> Options {
+		Options {
+			timeout,
+			max_retries,
+			min_delay: Duration::from_millis(10),
+			max_delay: Duration::from_millis(50),
+		}
+	}
+
+	/// Serve `status` for every request, counting how many were made.
+	async fn mock_portal(status: StatusCode) -> (String, Arc<AtomicUsize>) {
+		let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let addr = listener.local_addr().unwrap();
+		let hits = Arc::new(AtomicUsize::new(0));
+
+		let counter = hits.clone();
+		let app = axum::Router::new().fallback(move || {
+			counter.fetch_add(1, Ordering::SeqCst);
+			async move { status }
+		});
+		tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+		(format!("http://{addr}"), hits)
+	}
+
+	#[tokio::test]
+	async fn posts_to_the_configured_verify_path() {
+		let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let addr = listener.local_addr().unwrap();
+		let paths = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+		let seen = paths.clone();
+		let app = axum::Router::new().fallback(move |uri: axum::http::Uri| {
+			seen.lock().unwrap().push(uri.path().to_string());
+			async move { StatusCode::OK }
+		});
+		tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+		let result = dev_portal_verify_proof(
+			proof(),
+			"app_test".to_string(),
+			"username",
+			String::new(),
+			format!("http://{addr}"),
+			"api/v3/verify",
+			options(Duration::from_secs(5), 0),
+		)
+		.await;
+
+		assert!(result.is_ok());
+		assert_eq!(*paths.lock().unwrap(), ["/api/v3/verify/app_test"]);
+	}
+
+	/// Reject every request with a 400 and `body`.
+	async fn rejecting_portal(body: &'static str) -> String {
+		let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let addr = listener.local_addr().unwrap();
+
+		let app =
+			axum::Router::new().fallback(move || async move { (StatusCode::BAD_REQUEST, body) });
+		tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+		format!("http://{addr}")
+	}
+
+	async fn verify_against(url: String) -> Result<(), Error> {
+		dev_portal_verify_proof(
+			proof(),
+			"app_test".to_string(),
+			"username",
+			String::new(),
+			url,
+			"api/v2/verify",
+			options(Duration::from_secs(5), 2),
+		)
+		.await
+	}
+
+	#[tokio::test]
+	async fn keeps_the_body_of_unexpected_rejections() {
+		let url = rejecting_portal("<html>Bad Request</html>").await;
+
+		let result = verify_against(url).await;
+
+		assert!(
+			matches!(&result, Err(Error::UnexpectedRejection(body)) if body == "<html>Bad Request</html>"),
+			"{result:?}"
+		);
+	}
+
+	#[tokio::test]
+	async fn decodes_expected_rejections() {
+		let url = rejecting_portal(
+			r#"{"code":"invalid_proof","detail":"The provided proof is invalid.","attribute":null}"#,
+		)
+		.await;
+
+		let result = verify_against(url).await;
+
+		assert!(
+			matches!(&result, Err(Error::Verification(e)) if e.code == "invalid_proof"),
+			"{result:?}"
+		);
+	}
+
+	#[test]
+	fn backs_off_exponentially_up_to_the_max_delay() {
+		let options = options(Duration::from_secs(1), 5);
+
+		assert_eq!(options.retry_delay(0), Duration::from_millis(10));
+		assert_eq!(options.retry_delay(1), Duration::from_millis(20));
+		assert_eq!(options.retry_delay(2), Duration::from_millis(40));
+		assert_eq!(options.retry_delay(3), Duration::from_millis(50));
+		assert_eq!(options.retry_delay(u32::MAX), Duration::from_millis(50));
+	}
+
+	#[tokio::test]
+	async fn times_out_within_budget_when_portal_keeps_failing() {
+		let (url, hits) = mock_portal(StatusCode::INTERNAL_SERVER_ERROR).await;
+		let timeout = Duration::from_millis(300);
+
+		let started = Instant::now();
+		let result = dev_portal_verify_proof(
+			proof(),
+			"app_test".to_string(),
+			"username",
+			String::new(),
+			url,
+			"api/v2/verify",
+			options(timeout, u32::MAX),
+		)
+		.await;
+
+		assert!(matches!(result, Err(Error::Timeout(t)) if t == timeout));
+		assert!(started.elapsed() < timeout + Duration::from_millis(500));
+		assert!(hits.load(Ordering::SeqCst) > 1);
+	}
+
+	#[tokio::test]
+	async fn stops_after_max_retries() {
+		let (url, hits) = mock_portal(StatusCode::BAD_GATEWAY).await;
+
+		let result = dev_portal_verify_proof(
+			proof(),
+			"app_test".to_string(),
+			"username",
+			String::new(),
+			url,
+			"api/v2/verify",
+			options(Duration::from_secs(5), 2),
+		)
+		.await;
+
+		assert!(matches!(result, Err(Error::InvalidResponse(_))));
+		assert_eq!(hits.load(Ordering::SeqCst), 3);
+	}
+
+	#[tokio::test]
+	async fn does_not_retry_rejections() {
+		let (url, hits) = mock_portal(StatusCode::BAD_REQUEST).await;
+
+		let result = dev_portal_verify_proof(
+			proof(),
+			"app_test".to_string(),
+			"username",
+			String::new(),
+			url,
+			"api/v2/verify",
+			options(Duration::from_secs(5), 2),
+		)
+		.await;
+
+		assert!(matches!(result, Err(Error::UnexpectedRejection(_))));
+		assert_eq!(hits.load(Ordering::SeqCst), 1);
+	}
+}